@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use hyper::{Body, Response, StatusCode};
+use log::info;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use super::error::{self, HttpResult};
+use super::jwt::{Algorithm, Jwt};
+use super::{
+    conflict, json_request, json_response, not_found, too_many_requests, unauthorized, unix_millis,
+    unix_time, Context,
+};
+use crate::model::{Account, ADMIN_ROLE};
+
+/// A hash of a random password, which is verified for unknown users
+///
+/// Its parameters match the ones of new hashes, so the response time does not
+/// reveal whether a user exists.
+const DUMMY_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$b4C38HJHO0QEdfcW8THpIA$vcL0SNLNZ1EaWHJ/uxVPOpLtV2mc9SWFMJk5Hcq+0Lw";
+
+const MAX_USER_ID_LEN: usize = 64;
+const MIN_PASSWORD_LEN: usize = 8;
+const MAX_PASSWORD_LEN: usize = 128;
+
+/// Entries of the throttle after which expired ones are removed, then the least recent
+const MAX_THROTTLE_ENTRIES: usize = 10_000;
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Credentials {
+    user_id: String,
+    password: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct LoginResponse {
+    token: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct AccountResponse {
+    user_id: String,
+    roles: Vec<String>,
+    created_at: u128,
+}
+
+impl From<Account> for AccountResponse {
+    fn from(account: Account) -> Self {
+        Self {
+            user_id: account.user_id,
+            roles: account.roles,
+            created_at: account.created_at,
+        }
+    }
+}
+
+pub(super) async fn handle_login(
+    context: &Context,
+    address: IpAddr,
+    req: Body,
+) -> HttpResult<Response<Body>> {
+    let payload = json_request::<Credentials>(req).await?;
+    let Some(key) = context.keys().current() else {
+        // Tokens are only issued by an identity provider
+        return not_found();
+    };
+
+    if let Err(retry_after) = context.try_login(&payload.user_id, address, unix_millis()) {
+        info!(
+            target: "audit",
+            "Throttled login of {} from {}", payload.user_id, address
+        );
+        return too_many_requests(retry_after);
+    }
+
+    let account = context.read_account(&payload.user_id).await;
+    let hash = account
+        .as_ref()
+        .map_or(DUMMY_HASH, |account| account.password_hash.as_str())
+        .to_string();
+    let valid = verify_password(payload.password, hash).await?;
+    let Some(account) = account.filter(|_| valid) else {
+        info!(
+            target: "audit",
+            "Failed login of {} from {}", payload.user_id, address
+        );
+        return unauthorized();
+    };
+    context.record_login_success(&account.user_id, address);
+
+    let config = context.config();
+    let mut roles = account.roles;
+    if config.admins.contains(&account.user_id) && !roles.iter().any(|role| role == ADMIN_ROLE) {
+        roles.push(ADMIN_ROLE.to_string());
+    }
+    let token = Jwt::new(Algorithm::HmacSha256, account.user_id)
+        .with_lifetime(unix_time(), config.token_lifetime)
+        .with_issuer(config.token_issuer.clone())
+        .with_audience(config.token_audience.clone())
+        .with_roles(roles)
+        .encode_with_key(key)?;
+    let res = json_response(StatusCode::OK, &LoginResponse { token })?;
+    Ok(res)
+}
+
+pub(super) async fn handle_register(context: &Context, req: Body) -> HttpResult<Response<Body>> {
+    if !context.config().registration {
+        return not_found();
+    }
+
+    let payload = json_request::<Credentials>(req).await?;
+    // Admins receive their role on login, so only other admins provision them
+    if context.config().admins.contains(&payload.user_id) {
+        info!(
+            target: "audit",
+            "Refused registration of admin {}", payload.user_id
+        );
+        return conflict();
+    }
+
+    let account = new_account(payload.user_id, payload.password, Vec::new()).await?;
+    let Some(account) = context.register(account).await? else {
+        return conflict();
+    };
+    info!("{} registered", account.user_id);
+
+    let res = json_response(StatusCode::CREATED, &AccountResponse::from(account))?;
+    Ok(res)
+}
+
+/// Creates an account with a hash of the password, which fails for invalid credentials
+pub(super) async fn new_account(
+    user_id: String,
+    password: String,
+    roles: Vec<String>,
+) -> HttpResult<Account> {
+    if !is_valid_user_id(&user_id) || !is_valid_password(&password) {
+        return Err(Box::new(error::BadRequest));
+    }
+
+    Ok(Account {
+        user_id,
+        password_hash: hash_password(password).await?,
+        roles,
+        created_at: u128::from(unix_millis()) * 1_000_000,
+    })
+}
+
+fn is_valid_user_id(user_id: &str) -> bool {
+    !user_id.is_empty()
+        && user_id.len() <= MAX_USER_ID_LEN
+        && !user_id.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+fn is_valid_password(password: &str) -> bool {
+    (MIN_PASSWORD_LEN..=MAX_PASSWORD_LEN).contains(&password.chars().count())
+}
+
+/// Hashes a password with Argon2id on a blocking thread
+async fn hash_password(password: String) -> anyhow::Result<String> {
+    let mut salt = [0; 16];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| anyhow::anyhow!("Failed to generate random bytes"))?;
+
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::encode_b64(&salt).map_err(anyhow::Error::msg)?;
+        let hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(anyhow::Error::msg)?;
+        Ok(hash.to_string())
+    })
+    .await?
+}
+
+/// Verifies a password against its hash on a blocking thread
+async fn verify_password(password: String, hash: String) -> anyhow::Result<bool> {
+    let valid = tokio::task::spawn_blocking(move || {
+        let Ok(hash) = PasswordHash::new(&hash) else {
+            return false;
+        };
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+    .await?;
+    Ok(valid)
+}
+
+/// Locks accounts and IP addresses after too many failed logins
+///
+/// Every attempt counts as a failure until it succeeds, so parallel attempts
+/// cannot exceed the limit. A lock is lifted once no login failed for the
+/// lockout duration.
+#[derive(Debug)]
+pub struct LoginThrottle {
+    max_failures: u32,
+    max_failures_per_address: u32,
+    /// Milliseconds after the last failure until the failures are forgotten
+    lockout: u64,
+    capacity: usize,
+    accounts: HashMap<String, Failures>,
+    addresses: HashMap<IpAddr, Failures>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: u32,
+    /// Milliseconds since the epoch
+    last_at: u64,
+}
+
+impl LoginThrottle {
+    pub fn new(max_failures: u32, max_failures_per_address: u32, lockout: u64) -> Self {
+        Self {
+            max_failures,
+            max_failures_per_address,
+            lockout,
+            capacity: MAX_THROTTLE_ENTRIES,
+            accounts: HashMap::new(),
+            addresses: HashMap::new(),
+        }
+    }
+
+    /// Counts a login attempt, or returns the seconds until one is allowed again
+    ///
+    /// A full throttle only makes room by forgetting accounts and addresses
+    /// which are not locked, otherwise the attempt is refused.
+    pub fn try_acquire(&mut self, user_id: &str, address: IpAddr, now: u64) -> Result<(), u64> {
+        if let Some(retry_after) = self.retry_after(user_id, address, now) {
+            return Err(retry_after);
+        }
+
+        let user_id = user_id.to_string();
+        let (lockout, capacity) = (self.lockout, self.capacity);
+        let account = Self::vacate(
+            &mut self.accounts,
+            &user_id,
+            now,
+            lockout,
+            self.max_failures,
+            capacity,
+        );
+        let address_entry = Self::vacate(
+            &mut self.addresses,
+            &address,
+            now,
+            lockout,
+            self.max_failures_per_address,
+            capacity,
+        );
+        if let Some(until) = account.err().max(address_entry.err()) {
+            return Err(retry_seconds(until, now));
+        }
+
+        Self::increment(&mut self.accounts, user_id, now, lockout);
+        Self::increment(&mut self.addresses, address, now, lockout);
+        Ok(())
+    }
+
+    /// Forgets the failures of an account and takes back the attempt counted for its address
+    pub fn record_success(&mut self, user_id: &str, address: IpAddr) {
+        self.accounts.remove(user_id);
+        if let Some(failures) = self.addresses.get_mut(&address) {
+            failures.count = failures.count.saturating_sub(1);
+        }
+    }
+
+    /// Returns the seconds until a login is allowed again, if the account or address is locked
+    fn retry_after(&self, user_id: &str, address: IpAddr, now: u64) -> Option<u64> {
+        let account = self.locked_until(self.accounts.get(user_id), self.max_failures, now);
+        let address = self.locked_until(
+            self.addresses.get(&address),
+            self.max_failures_per_address,
+            now,
+        );
+        let until = match (account, address) {
+            (Some(account), Some(address)) => u64::max(account, address),
+            (account, address) => account.or(address)?,
+        };
+        Some(retry_seconds(until, now))
+    }
+
+    fn locked_until(&self, failures: Option<&Failures>, max: u32, now: u64) -> Option<u64> {
+        let failures = failures?;
+        let until = failures.last_at + self.lockout;
+        (failures.count >= max && now < until).then_some(until)
+    }
+
+    /// Makes room for the key, or returns when the first lock ends if all entries are locked
+    fn vacate<K: Eq + Hash + Clone>(
+        failures: &mut HashMap<K, Failures>,
+        key: &K,
+        now: u64,
+        lockout: u64,
+        max: u32,
+        capacity: usize,
+    ) -> Result<(), u64> {
+        if failures.len() < capacity || failures.contains_key(key) {
+            return Ok(());
+        }
+
+        failures.retain(|_, failures| now < failures.last_at + lockout);
+        if failures.len() < capacity {
+            return Ok(());
+        }
+
+        let unlocked = failures
+            .iter()
+            .filter(|(_, failures)| failures.count < max)
+            .min_by_key(|(_, failures)| failures.last_at)
+            .map(|(key, _)| key.clone());
+        match unlocked {
+            Some(unlocked) => {
+                failures.remove(&unlocked);
+                Ok(())
+            }
+            None => Err(failures
+                .values()
+                .map(|failures| failures.last_at + lockout)
+                .min()
+                .unwrap_or(now)),
+        }
+    }
+
+    fn increment<K: Eq + Hash>(
+        failures: &mut HashMap<K, Failures>,
+        key: K,
+        now: u64,
+        lockout: u64,
+    ) {
+        let entry = failures.entry(key).or_insert(Failures {
+            count: 0,
+            last_at: now,
+        });
+        if now >= entry.last_at + lockout {
+            entry.count = 0;
+        }
+        entry.count += 1;
+        entry.last_at = now;
+    }
+}
+
+/// Rounds the milliseconds until the given time up to seconds
+fn retry_seconds(until: u64, now: u64) -> u64 {
+    (until.saturating_sub(now) + 999) / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    const OTHER_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+    #[tokio::test]
+    async fn it_should_verify_a_password() {
+        let hash = hash_password("correct horse".into()).await.unwrap();
+
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse".into(), hash.clone())
+            .await
+            .unwrap());
+        assert!(!verify_password("battery staple".into(), hash)
+            .await
+            .unwrap());
+        assert!(!verify_password("correct horse".into(), "plain".into())
+            .await
+            .unwrap());
+    }
+
+    #[test]
+    fn it_should_parse_the_dummy_hash() {
+        let hash = PasswordHash::new(DUMMY_HASH).unwrap();
+        let params = argon2::Params::try_from(&hash).unwrap();
+
+        let default = argon2::Params::default();
+
+        assert_eq!(argon2::Algorithm::default().ident(), hash.algorithm);
+        assert_eq!(default.m_cost(), params.m_cost());
+        assert_eq!(default.t_cost(), params.t_cost());
+        assert_eq!(default.p_cost(), params.p_cost());
+    }
+
+    #[test]
+    fn it_should_validate_credentials() {
+        assert!(is_valid_user_id("alice"));
+        assert!(!is_valid_user_id(""));
+        assert!(!is_valid_user_id("alice smith"));
+        assert!(!is_valid_user_id(&"a".repeat(MAX_USER_ID_LEN + 1)));
+
+        assert!(is_valid_password("12345678"));
+        assert!(!is_valid_password("1234567"));
+        assert!(!is_valid_password(&"a".repeat(MAX_PASSWORD_LEN + 1)));
+    }
+
+    #[test]
+    fn it_should_lock_an_account() {
+        let mut throttle = LoginThrottle::new(2, 10, 1_000);
+
+        assert_eq!(Ok(()), throttle.try_acquire("alice", ADDRESS, 0));
+        assert_eq!(Ok(()), throttle.try_acquire("alice", OTHER_ADDRESS, 100));
+        assert_eq!(Err(1), throttle.try_acquire("alice", ADDRESS, 100));
+        assert_eq!(Ok(()), throttle.try_acquire("bob", ADDRESS, 100));
+        assert_eq!(Ok(()), throttle.try_acquire("alice", ADDRESS, 1_100));
+
+        throttle.record_success("alice", ADDRESS);
+        assert_eq!(Ok(()), throttle.try_acquire("alice", ADDRESS, 1_100));
+        assert_eq!(Ok(()), throttle.try_acquire("alice", ADDRESS, 1_100));
+        assert_eq!(Err(1), throttle.try_acquire("alice", ADDRESS, 1_100));
+    }
+
+    #[test]
+    fn it_should_lock_an_address() {
+        let mut throttle = LoginThrottle::new(10, 2, 3_000);
+
+        assert_eq!(Ok(()), throttle.try_acquire("alice", ADDRESS, 0));
+        assert_eq!(Ok(()), throttle.try_acquire("bob", ADDRESS, 0));
+        assert_eq!(Err(3), throttle.try_acquire("carol", ADDRESS, 0));
+        assert_eq!(Ok(()), throttle.try_acquire("carol", OTHER_ADDRESS, 0));
+
+        throttle.record_success("bob", ADDRESS);
+        assert_eq!(Ok(()), throttle.try_acquire("bob", ADDRESS, 1_000));
+        assert_eq!(Err(3), throttle.try_acquire("bob", ADDRESS, 1_000));
+    }
+
+    #[test]
+    fn it_should_forget_unlocked_failures_when_full() {
+        let mut throttle = LoginThrottle::new(2, 10, 1_000);
+        throttle.capacity = 2;
+
+        assert_eq!(Ok(()), throttle.try_acquire("alice", ADDRESS, 0));
+        assert_eq!(Ok(()), throttle.try_acquire("alice", ADDRESS, 1));
+        assert_eq!(Ok(()), throttle.try_acquire("bob", OTHER_ADDRESS, 2));
+        assert_eq!(Ok(()), throttle.try_acquire("carol", OTHER_ADDRESS, 3));
+        assert_eq!(2, throttle.accounts.len());
+        assert_eq!(None, throttle.accounts.get("bob").map(|f| f.count));
+        assert_eq!(Err(1), throttle.try_acquire("alice", OTHER_ADDRESS, 3));
+    }
+
+    #[test]
+    fn it_should_refuse_logins_when_full_of_locks() {
+        let mut throttle = LoginThrottle::new(1, 10, 1_000);
+        throttle.capacity = 2;
+
+        assert_eq!(Ok(()), throttle.try_acquire("alice", ADDRESS, 0));
+        assert_eq!(Ok(()), throttle.try_acquire("bob", ADDRESS, 500));
+        assert_eq!(Err(1), throttle.try_acquire("carol", ADDRESS, 500));
+        assert_eq!(Ok(()), throttle.try_acquire("carol", ADDRESS, 1_000));
+        assert_eq!(Err(1), throttle.try_acquire("bob", ADDRESS, 1_000));
+    }
+}
@@ -0,0 +1,252 @@
+use hyper::{Body, Method, Request, Response, StatusCode};
+use log::info;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use super::account::{new_account, AccountResponse};
+use super::error::{self, HttpResult};
+use super::{
+    conflict, json_request, json_response, method_not_allowed, not_found, CancelPayload,
+    CancelResponse, Context,
+};
+use crate::model::{
+    AdjustBalance, AmendTrade, AmendTradeError, Amendment, Asset, ChangeState, MarketState,
+    TradeId, User,
+};
+
+/// Routes a request of an admin, the caller has to check the role
+pub(super) async fn handle_routing(
+    context: &Context,
+    user: &User,
+    req: Request<Body>,
+) -> HttpResult<Response<Body>> {
+    match (req.method(), req.uri().path()) {
+        (&Method::POST, "/admin/halt") => {
+            handle_change_state(context, user, ChangeState::Halt, req.into_body()).await
+        }
+        (_other_method, "/admin/halt") => method_not_allowed(&[Method::POST]),
+
+        (&Method::POST, "/admin/resume") => {
+            handle_change_state(context, user, ChangeState::Resume, req.into_body()).await
+        }
+        (_other_method, "/admin/resume") => method_not_allowed(&[Method::POST]),
+
+        (&Method::POST, "/admin/cancel") => handle_cancel(context, user, req.into_body()).await,
+        (_other_method, "/admin/cancel") => method_not_allowed(&[Method::POST]),
+
+        (&Method::POST, "/admin/balances") => {
+            handle_adjust_balance(context, user, req.into_body()).await
+        }
+        (_other_method, "/admin/balances") => method_not_allowed(&[Method::POST]),
+
+        (&Method::POST, "/admin/users") => handle_create_user(context, user, req.into_body()).await,
+        (_other_method, "/admin/users") => method_not_allowed(&[Method::POST]),
+
+        (&Method::POST, "/admin/bust") => handle_bust(context, user, req.into_body()).await,
+        (_other_method, "/admin/bust") => method_not_allowed(&[Method::POST]),
+
+        (&Method::POST, "/admin/correct") => handle_correct(context, user, req.into_body()).await,
+        (_other_method, "/admin/correct") => method_not_allowed(&[Method::POST]),
+
+        (&Method::POST, "/admin/snapshot") => handle_snapshot(context, user).await,
+        (_other_method, "/admin/snapshot") => method_not_allowed(&[Method::POST]),
+
+        (&Method::GET, "/admin/internals") => handle_get_internals(context).await,
+        (_other_method, "/admin/internals") => method_not_allowed(&[Method::GET]),
+
+        _ => not_found(),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct InstrumentPayload {
+    instrument: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct StateResponse {
+    state: MarketState,
+}
+
+async fn handle_change_state(
+    context: &Context,
+    user: &User,
+    command: ChangeState,
+    req: Body,
+) -> HttpResult<Response<Body>> {
+    let payload = json_request::<InstrumentPayload>(req).await?;
+    if payload.instrument != context.config().instrument {
+        return not_found();
+    }
+
+    info!(target: "audit", "{} requested {:?} of {}", user.id(), command, payload.instrument);
+    let state = context.change_state(command).await?;
+    let res = json_response(StatusCode::OK, &StateResponse { state })?;
+    Ok(res)
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct AdminCancelPayload {
+    #[serde(default)]
+    user_id: Option<String>,
+    #[serde(flatten)]
+    filter: CancelPayload,
+}
+
+async fn handle_cancel(context: &Context, user: &User, req: Body) -> HttpResult<Response<Body>> {
+    let payload = json_request::<AdminCancelPayload>(req).await?;
+    if payload.filter.is_other_instrument(context.config()) {
+        return not_found();
+    }
+
+    let instrument = payload.filter.instrument.clone();
+    let command = payload.filter.into_command(payload.user_id);
+    info!(
+        target: "audit",
+        "{} cancelled orders matching {:?} in {:?}",
+        user.id(),
+        command,
+        instrument
+    );
+    let order_ids = context.cancel_orders(command).await?;
+    let res = json_response(StatusCode::OK, &CancelResponse { order_ids })?;
+    Ok(res)
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct BalancePayload {
+    user_id: String,
+    asset: Asset,
+    amount: Decimal,
+}
+
+async fn handle_adjust_balance(
+    context: &Context,
+    user: &User,
+    req: Body,
+) -> HttpResult<Response<Body>> {
+    let payload = json_request::<BalancePayload>(req).await?;
+
+    info!(
+        target: "audit",
+        "{} adjusted {:?} balance of {} by {}",
+        user.id(),
+        payload.asset,
+        payload.user_id,
+        payload.amount
+    );
+    let command = AdjustBalance {
+        user_id: payload.user_id,
+        asset: payload.asset,
+        amount: payload.amount,
+    };
+    let balance = context.adjust_balance(command).await?;
+    let res = json_response(StatusCode::OK, &balance)?;
+    Ok(res)
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct UserPayload {
+    user_id: String,
+    password: String,
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+async fn handle_create_user(
+    context: &Context,
+    user: &User,
+    req: Body,
+) -> HttpResult<Response<Body>> {
+    let payload = json_request::<UserPayload>(req).await?;
+    let account = new_account(payload.user_id, payload.password, payload.roles).await?;
+
+    info!(
+        target: "audit",
+        "{} provisioned user {} with roles {:?}",
+        user.id(),
+        account.user_id,
+        account.roles
+    );
+    let Some(account) = context.register(account).await? else {
+        return conflict();
+    };
+    let res = json_response(StatusCode::CREATED, &AccountResponse::from(account))?;
+    Ok(res)
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct BustPayload {
+    trade_id: TradeId,
+}
+
+async fn handle_bust(context: &Context, user: &User, req: Body) -> HttpResult<Response<Body>> {
+    let payload = json_request::<BustPayload>(req).await?;
+    let command = AmendTrade {
+        trade_id: payload.trade_id,
+        amendment: Amendment::Bust,
+    };
+    handle_amend_trade(context, user, command).await
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct CorrectPayload {
+    trade_id: TradeId,
+    price: Decimal,
+    quantity: Decimal,
+}
+
+async fn handle_correct(context: &Context, user: &User, req: Body) -> HttpResult<Response<Body>> {
+    let payload = json_request::<CorrectPayload>(req).await?;
+    let command = AmendTrade {
+        trade_id: payload.trade_id,
+        amendment: Amendment::Correct {
+            price: payload.price,
+            quantity: payload.quantity,
+        },
+    };
+    handle_amend_trade(context, user, command).await
+}
+
+async fn handle_amend_trade(
+    context: &Context,
+    user: &User,
+    command: AmendTrade,
+) -> HttpResult<Response<Body>> {
+    if command.validate().is_err() {
+        return Err(Box::new(error::BadRequest));
+    }
+
+    info!(
+        target: "audit",
+        "{} amended trade {} with {:?}",
+        user.id(),
+        command.trade_id.0,
+        command.amendment
+    );
+    let amendment = match context.amend_trade(command).await? {
+        Ok(amendment) => amendment,
+        Err(AmendTradeError::UnknownTrade) => return not_found(),
+        Err(AmendTradeError::Busted) => return conflict(),
+    };
+    let res = json_response(StatusCode::OK, &amendment)?;
+    Ok(res)
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct SnapshotResponse {
+    wal_entries: usize,
+}
+
+async fn handle_snapshot(context: &Context, user: &User) -> HttpResult<Response<Body>> {
+    info!(target: "audit", "{} triggered a snapshot", user.id());
+    let wal_entries = context.take_snapshot().await?;
+    let res = json_response(StatusCode::OK, &SnapshotResponse { wal_entries })?;
+    Ok(res)
+}
+
+async fn handle_get_internals(context: &Context) -> HttpResult<Response<Body>> {
+    let internals = context.read_internals().await?;
+    let res = json_response(StatusCode::OK, &internals)?;
+    Ok(res)
+}
@@ -0,0 +1,291 @@
+use std::collections::{HashSet, VecDeque};
+
+use hmac::{Hmac, Mac};
+use hyper::{Body, Method, Response, StatusCode};
+use log::info;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::error::HttpResult;
+use super::jwt::base64;
+use super::{json_request, json_response, not_found, unix_millis, Context};
+use crate::model::{ApiKey, RevokeApiKey, Scope, User};
+
+/// The scheme of signed requests in the `Authorization` header
+pub const SCHEME: &str = "ApiKey ";
+
+/// The signature of a request by an API key
+///
+/// It is sent as `ApiKey key=<key>,timestamp=<millis>,nonce=<nonce>,signature=<signature>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestSignature {
+    pub key: String,
+    /// Milliseconds since the epoch
+    pub timestamp: u64,
+    pub nonce: String,
+    pub signature: String,
+}
+
+impl RequestSignature {
+    /// Parses the parameters after the scheme
+    pub fn parse(str: &str) -> Option<Self> {
+        let mut key = None;
+        let mut timestamp = None;
+        let mut nonce = None;
+        let mut signature = None;
+
+        for param in str.split(',') {
+            let (name, value) = param.trim().split_once('=')?;
+            match name {
+                "key" => key = Some(value.to_string()),
+                "timestamp" => timestamp = Some(value.parse().ok()?),
+                "nonce" => nonce = Some(value.to_string()),
+                "signature" => signature = Some(value.to_string()),
+                _ => return None,
+            }
+        }
+
+        let nonce = nonce.filter(|nonce: &String| !nonce.is_empty() && nonce.len() <= 64)?;
+        Some(Self {
+            key: key?,
+            timestamp: timestamp?,
+            nonce,
+            signature: signature?,
+        })
+    }
+
+    /// Verifies the signature in constant time
+    pub fn verify(&self, secret: &str, method: &Method, path: &str, body: &[u8]) -> bool {
+        let Some(signature) = base64::decode(&self.signature) else {
+            return false;
+        };
+        mac(secret, self.timestamp, &self.nonce, method, path, body)
+            .verify_slice(&signature)
+            .is_ok()
+    }
+}
+
+/// Signs a request with the secret of an API key
+#[cfg(test)]
+pub fn sign(
+    secret: &str,
+    timestamp: u64,
+    nonce: &str,
+    method: &Method,
+    path: &str,
+    body: &[u8],
+) -> String {
+    let mac = mac(secret, timestamp, nonce, method, path, body);
+    base64::encode(&mac.finalize().into_bytes())
+}
+
+/// Computes an HMAC-SHA256 over `timestamp\nnonce\nMETHOD\npath\nbody`
+fn mac(
+    secret: &str,
+    timestamp: u64,
+    nonce: &str,
+    method: &Method,
+    path: &str,
+    body: &[u8],
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("Create encoder");
+    mac.update(format!("{}\n{}\n{}\n{}\n", timestamp, nonce, method, path).as_bytes());
+    mac.update(body);
+    mac
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    UnknownKey,
+    /// The timestamp is outside of the receive window
+    Expired,
+    /// The nonce was already used
+    Replayed,
+    Signature,
+}
+
+/// Remembers the nonces of accepted requests to reject replays
+#[derive(Debug, Default)]
+pub struct NonceCache {
+    seen: HashSet<(String, String)>,
+    expiry: VecDeque<(u64, String, String)>,
+}
+
+impl NonceCache {
+    /// Remembers a nonce of a key until `expires_at` and returns whether it is new
+    pub fn insert(&mut self, key: &str, nonce: &str, now: u64, expires_at: u64) -> bool {
+        while let Some((at, _, _)) = self.expiry.front() {
+            if *at > now {
+                break;
+            }
+            let (_, key, nonce) = self.expiry.pop_front().unwrap();
+            self.seen.remove(&(key, nonce));
+        }
+
+        if !self.seen.insert((key.to_string(), nonce.to_string())) {
+            return false;
+        }
+        self.expiry
+            .push_back((expires_at, key.to_string(), nonce.to_string()));
+        true
+    }
+}
+
+/// Authenticates a request which is signed by an API key
+pub(super) async fn authenticate(
+    context: &Context,
+    signature: &RequestSignature,
+    method: &Method,
+    path: &str,
+    body: &[u8],
+) -> Result<User, SignatureError> {
+    let now = unix_millis();
+    let window = context.config().api_key_recv_window;
+    if now.abs_diff(signature.timestamp) > window {
+        return Err(SignatureError::Expired);
+    }
+
+    let key = context
+        .read_api_key(&signature.key)
+        .await
+        .ok_or(SignatureError::UnknownKey)?;
+    if !signature.verify(&key.secret, method, path, body) {
+        return Err(SignatureError::Signature);
+    }
+
+    // A replay is only possible while the timestamp is within the window
+    if !context.insert_nonce(&key.key, &signature.nonce, now, now + 2 * window) {
+        return Err(SignatureError::Replayed);
+    }
+
+    Ok(User::from_api_key(&key))
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct CreateApiKeyPayload {
+    scopes: Vec<Scope>,
+}
+
+/// An API key without its secret
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct ApiKeyResponse {
+    key: String,
+    scopes: Vec<Scope>,
+    created_at: u128,
+}
+
+pub(super) async fn handle_create(
+    context: &Context,
+    user: &User,
+    req: Body,
+) -> HttpResult<Response<Body>> {
+    let payload = json_request::<CreateApiKeyPayload>(req).await?;
+
+    let key = ApiKey {
+        key: random_string(16)?,
+        secret: random_string(32)?,
+        user_id: user.id().to_string(),
+        scopes: payload.scopes,
+        created_at: u128::from(unix_millis()) * 1_000_000,
+    };
+    let key = context.create_api_key(key).await?;
+    info!("{} created API key {}", user.id(), key.key);
+
+    let res = json_response(StatusCode::CREATED, &key)?;
+    Ok(res)
+}
+
+pub(super) async fn handle_list(context: &Context, user: &User) -> HttpResult<Response<Body>> {
+    let mut keys = context
+        .read_api_keys(user.id())
+        .await
+        .into_iter()
+        .map(|key| ApiKeyResponse {
+            key: key.key,
+            scopes: key.scopes,
+            created_at: key.created_at,
+        })
+        .collect::<Vec<_>>();
+    keys.sort_by_key(|key| key.created_at);
+
+    let res = json_response(StatusCode::OK, &keys)?;
+    Ok(res)
+}
+
+pub(super) async fn handle_revoke(
+    context: &Context,
+    user: &User,
+    key: &str,
+) -> HttpResult<Response<Body>> {
+    let command = RevokeApiKey {
+        user_id: user.id().to_string(),
+        key: key.to_string(),
+    };
+    let Some(key) = context.revoke_api_key(command).await? else {
+        return not_found();
+    };
+    info!("{} revoked API key {}", user.id(), key.key);
+
+    let mut res = Response::default();
+    *res.status_mut() = StatusCode::NO_CONTENT;
+    Ok(res)
+}
+
+fn random_string(len: usize) -> anyhow::Result<String> {
+    let mut bytes = vec![0; len];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("Failed to generate random bytes"))?;
+    Ok(base64::encode(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_a_signature() {
+        let expected = RequestSignature {
+            key: "abc".into(),
+            timestamp: 1_000,
+            nonce: "1".into(),
+            signature: "c2ln".into(),
+        };
+        let actual = RequestSignature::parse("key=abc, timestamp=1000, nonce=1, signature=c2ln");
+
+        assert_eq!(Some(expected), actual);
+        assert_eq!(None, RequestSignature::parse("key=abc,timestamp=1000"));
+        assert_eq!(
+            None,
+            RequestSignature::parse("key=abc,timestamp=now,nonce=1,signature=c2ln")
+        );
+    }
+
+    #[test]
+    fn it_should_verify_a_signature() {
+        let signature = sign("secret", 1_000, "1", &Method::POST, "/orders", b"{}");
+        let request = RequestSignature {
+            key: "abc".into(),
+            timestamp: 1_000,
+            nonce: "1".into(),
+            signature,
+        };
+
+        assert!(request.verify("secret", &Method::POST, "/orders", b"{}"));
+        assert!(!request.verify("other", &Method::POST, "/orders", b"{}"));
+        assert!(!request.verify("secret", &Method::GET, "/orders", b"{}"));
+        assert!(!request.verify("secret", &Method::POST, "/", b"{}"));
+        assert!(!request.verify("secret", &Method::POST, "/orders", b"{ }"));
+    }
+
+    #[test]
+    fn it_should_reject_a_reused_nonce() {
+        let mut cache = NonceCache::default();
+
+        assert!(cache.insert("abc", "1", 0, 10));
+        assert!(!cache.insert("abc", "1", 5, 15));
+        assert!(cache.insert("def", "1", 5, 15));
+        assert!(cache.insert("abc", "1", 10, 20));
+    }
+}
@@ -1,34 +1,83 @@
-use anyhow::Result;
-use futures::{stream, Stream};
+use anyhow::{anyhow, Context as _, Result};
+use futures::{future, stream, Stream, StreamExt};
 use hyper::Method;
+use log::{error, info, warn};
 use prometheus::proto::MetricFamily;
-use prometheus::{HistogramOpts, HistogramVec, IntGauge, Registry};
-use std::sync::Arc;
-use std::time::Duration;
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry};
+use std::fs;
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::watch::Receiver;
 use tokio::sync::{RwLock, RwLockReadGuard};
 
+use super::account::LoginThrottle;
+use super::api_key::NonceCache;
 use super::buckets::netflix_buckets;
-use crate::model::{MessageChannel, MessagePort, OpenOrder, Order, OrderBook, State, Trade};
+use super::jwt::{read_jwks, Algorithm, KeySet, SigningKey};
+use super::rate_limit::{RateLimits, RouteClass};
+use super::session::{Session, Sessions};
+use crate::config::Config;
+use crate::model::{
+    Account, AdjustBalance, AmendTrade, AmendTradeError, ApiKey, ArchiveReader, Balance, BatchItem,
+    BatchResult, CancelOrders, Candle, ChangeState, Command, ExecutionReport, Internals, Interval,
+    L3Event, L3Snapshot, MarketState, MessageChannel, OpenOrder, Order, OrderBook, OrderId,
+    ReadInternals, ReadL3, RevokeApiKey, State, TakeSnapshot, TapeEvent, TapeTrade, TickerStats,
+    TradeAmendment, TradePage, MAX_TRADE_PAGE,
+};
 
 #[derive(Debug, Clone)]
 pub struct Context {
+    config: Arc<Config>,
+    keys: Arc<KeySet>,
+    algorithms: Arc<Vec<Algorithm>>,
+    nonces: Arc<Mutex<NonceCache>>,
+    logins: Arc<Mutex<LoginThrottle>>,
+    rate_limits: Arc<Mutex<RateLimits>>,
+    sessions: Arc<Mutex<Sessions>>,
     registry: Registry,
     req_duration_histogram: HistogramVec,
+    rate_limited_counter: IntCounterVec,
     connection_gauge: IntGauge,
     order_book_receiver: Receiver<OrderBook>,
-    matcher: Sender<MessagePort<OpenOrder, Order>>,
+    report_sender: broadcast::Sender<ExecutionReport>,
+    l3_sender: broadcast::Sender<L3Event>,
+    matcher: Sender<Command>,
     state: Arc<RwLock<State>>,
+    archive: ArchiveReader,
 }
 
 impl Context {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        config: Config,
         registry: Registry,
         order_book_receiver: Receiver<OrderBook>,
-        matcher: Sender<MessagePort<OpenOrder, Order>>,
+        report_sender: broadcast::Sender<ExecutionReport>,
+        l3_sender: broadcast::Sender<L3Event>,
+        matcher: Sender<Command>,
         state: Arc<RwLock<State>>,
+        archive: ArchiveReader,
     ) -> Result<Self> {
+        let keys = load_keys(&config)?;
+        let algorithms = config
+            .token_algorithms
+            .iter()
+            .map(|algorithm| algorithm.parse())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| anyhow!("Invalid APP_TOKEN_ALGORITHMS: {}", err))?;
+
+        let logins = LoginThrottle::new(
+            config.login_max_failures,
+            config.login_max_failures_per_address,
+            config.login_lockout * 1000,
+        );
+
+        let rate_limits = RateLimits::new(&config);
+
         let req_duration_histogram = HistogramVec::new(
             HistogramOpts::new(
                 "request_duration_seconds",
@@ -39,20 +88,150 @@ impl Context {
         )?;
         registry.register(Box::new(req_duration_histogram.clone()))?;
 
+        let rate_limited_counter = IntCounterVec::new(
+            Opts::new(
+                "rate_limited_requests_total",
+                "Number of requests rejected by a rate limit",
+            ),
+            &["class", "limit"],
+        )?;
+        registry.register(Box::new(rate_limited_counter.clone()))?;
+
         let connection_gauge = IntGauge::new("connected_clients", "Number of connected clients")?;
         registry.register(Box::new(connection_gauge.clone()))?;
 
         Ok(Self {
+            config: Arc::new(config),
+            keys: Arc::new(keys),
+            algorithms: Arc::new(algorithms),
+            nonces: Arc::new(Mutex::new(NonceCache::default())),
+            logins: Arc::new(Mutex::new(logins)),
+            rate_limits: Arc::new(Mutex::new(rate_limits)),
+            sessions: Arc::new(Mutex::new(Sessions::default())),
             registry,
             req_duration_histogram,
+            rate_limited_counter,
             connection_gauge,
             order_book_receiver,
+            report_sender,
+            l3_sender,
             matcher,
             state,
+            archive,
         })
     }
 
-    pub async fn read_order_book(&self) -> RwLockReadGuard<OrderBook> {
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn keys(&self) -> &KeySet {
+        &self.keys
+    }
+
+    /// Returns the algorithms which incoming tokens may be signed with
+    pub fn algorithms(&self) -> &[Algorithm] {
+        &self.algorithms
+    }
+
+    /// Remembers the nonce of a signed request and returns whether it is new
+    pub fn insert_nonce(&self, key: &str, nonce: &str, now: u64, expires_at: u64) -> bool {
+        let mut nonces = self.nonces.lock().unwrap();
+        nonces.insert(key, nonce, now, expires_at)
+    }
+
+    /// Counts a login attempt, or returns the seconds until one is allowed again
+    pub fn try_login(&self, user_id: &str, address: IpAddr, now: u64) -> Result<(), u64> {
+        let mut logins = self.logins.lock().unwrap();
+        logins.try_acquire(user_id, address, now)
+    }
+
+    pub fn record_login_success(&self, user_id: &str, address: IpAddr) {
+        let mut logins = self.logins.lock().unwrap();
+        logins.record_success(user_id, address);
+    }
+
+    /// Takes a token of the user's rate limit, or returns the seconds until one is available
+    pub fn acquire_user(&self, class: RouteClass, user_id: &str, now: u64) -> Result<(), u64> {
+        let mut rate_limits = self.rate_limits.lock().unwrap();
+        rate_limits
+            .acquire_user(class, user_id, now)
+            .map_err(|retry_after| self.inc_rate_limited(class, "user", retry_after))
+    }
+
+    /// Takes a token of the address' rate limit, or returns the seconds until one is available
+    pub fn acquire_address(&self, class: RouteClass, address: IpAddr, now: u64) -> Result<(), u64> {
+        let mut rate_limits = self.rate_limits.lock().unwrap();
+        rate_limits
+            .acquire_address(class, address, now)
+            .map_err(|retry_after| self.inc_rate_limited(class, "address", retry_after))
+    }
+
+    fn inc_rate_limited(&self, class: RouteClass, limit: &str, retry_after: u64) -> u64 {
+        self.rate_limited_counter
+            .with_label_values(&[class.as_str(), limit])
+            .inc();
+        retry_after
+    }
+
+    /// Registers a session which cancels the orders of the user when it is closed
+    pub fn open_session(&self, user_id: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.open(user_id);
+    }
+
+    /// Cancels the orders of the user after the grace period, unless another session is open then
+    pub fn close_session(&self, session: Session) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if !sessions.close(&session.user_id) {
+            return;
+        }
+
+        let context = self.clone();
+        let user_id = session.user_id.clone();
+        let pending = tokio::spawn(async move {
+            tokio::time::sleep(session.grace).await;
+            if !context.sessions.lock().unwrap().expire(&user_id) {
+                return;
+            }
+
+            info!("Cancelling orders of {} after disconnect", user_id);
+            let command = CancelOrders::of_user(user_id);
+            if let Err(err) = context.cancel_orders(command).await {
+                error!("Failed to cancel orders after disconnect: {}", err);
+            }
+        });
+        sessions.wait(&session.user_id, pending);
+    }
+
+    pub async fn read_account(&self, user_id: &str) -> Option<Account> {
+        let state = self.state.read().await;
+        state.accounts.get(user_id).cloned()
+    }
+
+    /// Returns the current state of an order by the client order ID of its owner
+    pub async fn read_client_order(&self, user_id: &str, client_order_id: &str) -> Option<Order> {
+        let state = self.state.read().await;
+        state.client_orders.get(user_id, client_order_id).cloned()
+    }
+
+    pub async fn read_api_key(&self, key: &str) -> Option<ApiKey> {
+        let state = self.state.read().await;
+        state.api_keys.get(key).cloned()
+    }
+
+    /// Returns the API keys of a user
+    pub async fn read_api_keys(&self, user_id: &str) -> Vec<ApiKey> {
+        let state = self.state.read().await;
+        state
+            .api_keys
+            .values()
+            .filter(|key| key.user_id == user_id)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn read_order_book(&self) -> RwLockReadGuard<'_, OrderBook> {
         let state = self.state.read().await;
         RwLockReadGuard::map(state, |s| &s.order_book)
     }
@@ -76,9 +255,174 @@ impl Context {
         )
     }
 
-    pub async fn read_trades(&self) -> RwLockReadGuard<Vec<Trade>> {
+    /// Streams the execution reports of a user, skipping the ones a slow subscriber missed
+    pub fn subscribe_execution_reports(
+        &self,
+        user_id: String,
+    ) -> impl Stream<Item = ExecutionReport> + Send + 'static {
+        broadcast_stream(self.report_sender.subscribe())
+            .filter(move |report| future::ready(report.user_id == user_id))
+    }
+
+    /// Reads all resting orders with the sequence of the last change of the L3 stream
+    pub async fn read_l3(&self) -> Result<L3Snapshot> {
+        let msg = MessageChannel::new(ReadL3);
+        let snapshot = msg.send_to(&self.matcher).await?;
+        Ok(snapshot)
+    }
+
+    /// Streams the changes of the resting orders, skipping the ones a slow subscriber missed
+    pub fn subscribe_l3(&self) -> impl Stream<Item = L3Event> + Send + 'static {
+        broadcast_stream(self.l3_sender.subscribe())
+    }
+
+    /// Streams the trades after a sequence and the amendments after a count of them
+    ///
+    /// Older trades are read from the archive, reporting the missing ones.
+    /// Amendments follow once the stream caught up with the trades they amend.
+    pub fn subscribe_trades(
+        &self,
+        after: usize,
+        amended: usize,
+    ) -> impl Stream<Item = Result<TapeEvent>> + Send + 'static {
+        let receiver = self.order_book_receiver.clone();
+        stream::unfold(
+            Some((after, amended, receiver, self.clone())),
+            |subscription| async move {
+                let (after, amended, mut receiver, context) = subscription?;
+                loop {
+                    let (trades, first, amendments) = {
+                        let state = context.state.read().await;
+                        let trades = state
+                            .trades
+                            .range(after + 1..=after.saturating_add(MAX_TRADE_PAGE));
+                        let amendments = state.amendments[amended..].to_vec();
+                        (trades, state.trades.first_sequence(), amendments)
+                    };
+                    let (trades, end) = match after + 1 < first {
+                        true => {
+                            let end = usize::min(first - 1, after.saturating_add(MAX_TRADE_PAGE));
+                            match context.read_archived_trades(after + 1..=end).await {
+                                Ok(archived) => (archived, end),
+                                Err(err) => {
+                                    error!("Failed to read archived trades: {}", err);
+                                    return Some((vec![Err(err)], None));
+                                }
+                            }
+                        }
+                        false => {
+                            let end = trades.last().map_or(after, TapeTrade::sequence);
+                            (trades, end)
+                        }
+                    };
+                    if end > after {
+                        let trades = context.state.read().await.with_amendments(trades);
+                        let events = with_missing_trades(after, end, trades);
+                        return Some((events, Some((end, amended, receiver, context))));
+                    }
+                    if !amendments.is_empty() {
+                        let amended = amended + amendments.len();
+                        let events = amendments
+                            .into_iter()
+                            .map(|amendment| Ok(TapeEvent::Amendment(amendment)))
+                            .collect();
+                        return Some((events, Some((after, amended, receiver, context))));
+                    }
+                    receiver.changed().await.ok()?;
+                }
+            },
+        )
+        .flat_map(stream::iter::<Vec<_>>)
+    }
+
+    pub async fn read_candles(
+        &self,
+        interval: Interval,
+        from: Option<u128>,
+        to: Option<u128>,
+    ) -> Vec<Candle> {
         let state = self.state.read().await;
-        RwLockReadGuard::map(state, |s| &s.trades)
+        state.candles.range(interval, from, to)
+    }
+
+    /// Streams the current candle of an interval whenever a trade updates it
+    pub fn subscribe_candles(
+        &self,
+        interval: Interval,
+    ) -> impl Stream<Item = Candle> + Send + 'static {
+        stream::unfold(
+            (None, self.order_book_receiver.clone(), self.state.clone()),
+            move |(last, mut receiver, state)| async move {
+                loop {
+                    let candle = state.read().await.candles.latest(interval).cloned();
+                    match candle {
+                        Some(candle) if last.as_ref() != Some(&candle) => {
+                            return Some((candle.clone(), (Some(candle), receiver, state)));
+                        }
+                        _ => receiver.changed().await.ok()?,
+                    }
+                }
+            },
+        )
+    }
+
+    /// Reads the statistics of the last 24 hours and the best prices
+    pub async fn read_ticker(&self) -> TickerStats {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let state = self.state.read().await;
+        state.ticker.stats(now, &state.order_book)
+    }
+
+    /// Reads a page of the trade history, older trades from the trade archive
+    pub async fn read_trades(&self, page: &TradePage) -> Result<Vec<TapeTrade>> {
+        let after = match page.from {
+            Some(from) => Some(self.executed_before(from).await),
+            None => None,
+        };
+        let before = match page.to {
+            Some(to) => Some(self.executed_before(to + 1).await + 1),
+            None => None,
+        };
+        let page = page.between(after, before);
+
+        let (range, trades, first) = {
+            let state = self.state.read().await;
+            let range = page.range(state.trades.count());
+            let trades = state.trades.range(range.clone());
+            (range, trades, state.trades.first_sequence())
+        };
+        let trades = match range.is_empty() || *range.start() >= first {
+            true => trades,
+            false => {
+                let end = usize::min(*range.end(), first - 1);
+                let mut archived = self.read_archived_trades(*range.start()..=end).await?;
+                archived.extend(trades);
+                archived
+            }
+        };
+        Ok(self.state.read().await.with_amendments(trades))
+    }
+
+    pub async fn trade_count(&self) -> usize {
+        self.state.read().await.trades.count()
+    }
+
+    pub async fn amendment_count(&self) -> usize {
+        self.state.read().await.amendments.len()
+    }
+
+    /// Returns the number of trades executed before the time, searching the archive if needed
+    async fn executed_before(&self, time: u128) -> usize {
+        let in_memory = self.state.read().await.trades.executed_before(time);
+        in_memory.unwrap_or_else(|| self.archive.executed_before(time))
+    }
+
+    async fn read_archived_trades(&self, range: RangeInclusive<usize>) -> Result<Vec<TapeTrade>> {
+        let archive = self.archive.clone();
+        tokio::task::spawn_blocking(move || archive.read(range)).await?
     }
 
     pub async fn open_order(&self, command: OpenOrder) -> Result<Order, anyhow::Error> {
@@ -87,6 +431,71 @@ impl Context {
         Ok(order)
     }
 
+    /// Processes the items back-to-back and returns their results in the same order
+    pub async fn process_batch(&self, items: Vec<BatchItem>) -> Result<Vec<BatchResult>> {
+        let msg = MessageChannel::new(items);
+        let results = msg.send_to(&self.matcher).await?;
+        Ok(results)
+    }
+
+    /// Cancels the matching orders and returns their IDs
+    pub async fn cancel_orders(&self, command: CancelOrders) -> Result<Vec<OrderId>> {
+        let msg = MessageChannel::new(command);
+        let orders = msg.send_to(&self.matcher).await?;
+        Ok(orders)
+    }
+
+    pub async fn change_state(&self, command: ChangeState) -> Result<MarketState> {
+        let msg = MessageChannel::new(command);
+        let state = msg.send_to(&self.matcher).await?;
+        Ok(state)
+    }
+
+    pub async fn adjust_balance(&self, command: AdjustBalance) -> Result<Balance> {
+        let msg = MessageChannel::new(command);
+        let balance = msg.send_to(&self.matcher).await?;
+        Ok(balance)
+    }
+
+    /// Busts or corrects a trade, unless it is unknown or busted already
+    pub async fn amend_trade(
+        &self,
+        command: AmendTrade,
+    ) -> Result<Result<TradeAmendment, AmendTradeError>> {
+        let msg = MessageChannel::new(command);
+        msg.send_to(&self.matcher).await?
+    }
+
+    pub async fn take_snapshot(&self) -> Result<usize> {
+        let msg = MessageChannel::new(TakeSnapshot);
+        msg.send_to(&self.matcher).await?
+    }
+
+    pub async fn create_api_key(&self, key: ApiKey) -> Result<ApiKey> {
+        let msg = MessageChannel::new(key);
+        let key = msg.send_to(&self.matcher).await?;
+        Ok(key)
+    }
+
+    pub async fn revoke_api_key(&self, command: RevokeApiKey) -> Result<Option<ApiKey>> {
+        let msg = MessageChannel::new(command);
+        let key = msg.send_to(&self.matcher).await?;
+        Ok(key)
+    }
+
+    /// Registers an account and returns `None` if the user ID is taken
+    pub async fn register(&self, account: Account) -> Result<Option<Account>> {
+        let msg = MessageChannel::new(account);
+        let account = msg.send_to(&self.matcher).await?;
+        Ok(account)
+    }
+
+    pub async fn read_internals(&self) -> Result<Internals> {
+        let msg = MessageChannel::new(ReadInternals);
+        let internals = msg.send_to(&self.matcher).await?;
+        Ok(internals)
+    }
+
     pub fn observe_req_duration(&self, method: &Method, path: &str, duration: Duration) {
         self.req_duration_histogram
             .with_label_values(&[method.as_str(), path])
@@ -105,3 +514,81 @@ impl Context {
         self.registry.gather()
     }
 }
+
+/// Loads the signing keys from the config and the key file
+fn load_keys(config: &Config) -> Result<KeySet> {
+    let mut keys = config
+        .signing_keys
+        .iter()
+        .map(|entry| SigningKey::parse(entry))
+        .collect::<Result<Vec<_>, _>>()
+        .context("Invalid APP_SIGNING_KEYS")?;
+
+    if let Some(path) = &config.signing_key_file {
+        let file = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        for line in file.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let key = SigningKey::parse(line)
+                .with_context(|| format!("Invalid signing key in {}", path.display()))?;
+            keys.push(key);
+        }
+    }
+
+    let mut public_keys = Vec::new();
+    if let Some(path) = &config.jwks_file {
+        let file = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        public_keys =
+            read_jwks(&file).with_context(|| format!("Invalid JWKS in {}", path.display()))?;
+    }
+
+    KeySet::new(keys, public_keys)
+        .context("Set APP_SIGNING_KEYS, APP_SIGNING_KEY_FILE or APP_JWKS_FILE")
+}
+
+/// Receives the messages of a broadcast channel, skipping the ones a slow receiver missed
+/// Turns trades into events, reporting the sequences missing up to the end
+fn with_missing_trades(after: usize, end: usize, trades: Vec<TapeTrade>) -> Vec<Result<TapeEvent>> {
+    let mut events = Vec::with_capacity(trades.len() + 1);
+    let mut next = after + 1;
+    for trade in trades {
+        let sequence = trade.sequence();
+        if sequence > next {
+            warn!(
+                "Skipping trades {} to {} missing in the archive",
+                next,
+                sequence - 1
+            );
+            events.push(Ok(TapeEvent::Missing {
+                missing: next..=sequence - 1,
+            }));
+        }
+        next = sequence + 1;
+        events.push(Ok(TapeEvent::Trade(trade)));
+    }
+    if next <= end {
+        warn!("Skipping trades {} to {} missing in the archive", next, end);
+        events.push(Ok(TapeEvent::Missing {
+            missing: next..=end,
+        }));
+    }
+    events
+}
+
+fn broadcast_stream<T: Clone + Send + 'static>(
+    receiver: broadcast::Receiver<T>,
+) -> impl Stream<Item = T> + Send + 'static {
+    stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(message) => return Some((message, receiver)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+}
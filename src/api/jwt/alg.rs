@@ -1,6 +1,13 @@
 use hmac::{Hmac, Mac};
+use ring::signature::{
+    RsaPublicKeyComponents, UnparsedPublicKey, ECDSA_P256_SHA256_FIXED, ED25519,
+    RSA_PKCS1_2048_8192_SHA256,
+};
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Sha384, Sha512};
+use std::str::FromStr;
+
+use super::{PublicKeyData, VerifyingKey};
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Algorithm {
@@ -10,11 +17,30 @@ pub enum Algorithm {
     HmacSha384,
     #[serde(rename = "HS512")]
     HmacSha512,
+    #[serde(rename = "RS256")]
+    RsaSha256,
+    #[serde(rename = "ES256")]
+    EcdsaSha256,
+    #[serde(rename = "EdDSA")]
+    EdDsa,
 }
 
 impl Algorithm {
-    pub fn encode(&self, body: &[u8], secret: &[u8]) -> String {
-        match self {
+    #[cfg(test)]
+    pub const ALL: &'static [Algorithm] = &[
+        Algorithm::HmacSha256,
+        Algorithm::HmacSha384,
+        Algorithm::HmacSha512,
+        Algorithm::RsaSha256,
+        Algorithm::EcdsaSha256,
+        Algorithm::EdDsa,
+    ];
+
+    /// Signs the body with a secret
+    ///
+    /// Returns `None` for asymmetric algorithms, which the engine can only verify.
+    pub fn encode(&self, body: &[u8], secret: &[u8]) -> Option<String> {
+        let signature = match self {
             Algorithm::HmacSha256 => {
                 let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("Create encoder");
                 mac.update(body);
@@ -33,6 +59,72 @@ impl Algorithm {
                 let result = mac.finalize();
                 super::base64::encode(&result.into_bytes())
             }
+            Algorithm::RsaSha256 | Algorithm::EcdsaSha256 | Algorithm::EdDsa => return None,
+        };
+        Some(signature)
+    }
+
+    /// Verifies the signature of the body, which fails if the key does not fit the algorithm
+    ///
+    /// Signatures are compared in constant time.
+    pub fn verify(&self, body: &[u8], signature: &str, key: VerifyingKey) -> bool {
+        let Some(signature) = super::base64::decode(signature) else {
+            return false;
+        };
+        match (self, key) {
+            (Algorithm::HmacSha256, VerifyingKey::Secret(secret)) => {
+                let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret) else {
+                    return false;
+                };
+                mac.update(body);
+                mac.verify_slice(&signature).is_ok()
+            }
+            (Algorithm::HmacSha384, VerifyingKey::Secret(secret)) => {
+                let Ok(mut mac) = Hmac::<Sha384>::new_from_slice(secret) else {
+                    return false;
+                };
+                mac.update(body);
+                mac.verify_slice(&signature).is_ok()
+            }
+            (Algorithm::HmacSha512, VerifyingKey::Secret(secret)) => {
+                let Ok(mut mac) = Hmac::<Sha512>::new_from_slice(secret) else {
+                    return false;
+                };
+                mac.update(body);
+                mac.verify_slice(&signature).is_ok()
+            }
+            (Algorithm::RsaSha256, VerifyingKey::Public(PublicKeyData::Rsa { n, e })) => {
+                RsaPublicKeyComponents { n, e }
+                    .verify(&RSA_PKCS1_2048_8192_SHA256, body, &signature)
+                    .is_ok()
+            }
+            (Algorithm::EcdsaSha256, VerifyingKey::Public(PublicKeyData::Ecdsa(point))) => {
+                UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point)
+                    .verify(body, &signature)
+                    .is_ok()
+            }
+            (Algorithm::EdDsa, VerifyingKey::Public(PublicKeyData::Ed25519(x))) => {
+                UnparsedPublicKey::new(&ED25519, x)
+                    .verify(body, &signature)
+                    .is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HS256" => Ok(Algorithm::HmacSha256),
+            "HS384" => Ok(Algorithm::HmacSha384),
+            "HS512" => Ok(Algorithm::HmacSha512),
+            "RS256" => Ok(Algorithm::RsaSha256),
+            "ES256" => Ok(Algorithm::EcdsaSha256),
+            "EdDSA" => Ok(Algorithm::EdDsa),
+            _ => Err(format!("unknown algorithm {s}")),
         }
     }
 }
@@ -45,7 +137,7 @@ mod tests {
     fn it_encodes_hs256() {
         let algorithm = Algorithm::HmacSha256;
         assert_eq!(
-            "c0zGLzKEFWj0VxWuufTXiRMk5tlI5MbGDAYhzaxIYjo".to_string(),
+            Some("c0zGLzKEFWj0VxWuufTXiRMk5tlI5MbGDAYhzaxIYjo".to_string()),
             algorithm.encode(b"hello world", b"secret")
         );
     }
@@ -54,7 +146,7 @@ mod tests {
     fn it_encodes_hs384() {
         let algorithm = Algorithm::HmacSha384;
         assert_eq!(
-            "LaO7F3uSqumMOrInJ9f2DJBb4br_cftLAKbkEJI-ZVg3ZZDB-vki_1HsSb53QJrG".to_string(),
+            Some("LaO7F3uSqumMOrInJ9f2DJBb4br_cftLAKbkEJI-ZVg3ZZDB-vki_1HsSb53QJrG".to_string()),
             algorithm.encode(b"hello world", b"secret")
         );
     }
@@ -63,8 +155,44 @@ mod tests {
     fn it_encodes_hs512() {
         let algorithm = Algorithm::HmacSha512;
         assert_eq!(
-            "bTIjmwHdF1BVchFikxPZXk9Py47lF-RDmQrBr8dWK_10_6YRg4fv2eFo_4bR2lzvSlXtxjzEuiicTDqLT3vfwg".to_string(),
+            Some("bTIjmwHdF1BVchFikxPZXk9Py47lF-RDmQrBr8dWK_10_6YRg4fv2eFo_4bR2lzvSlXtxjzEuiicTDqLT3vfwg".to_string()),
             algorithm.encode(b"hello world", b"secret")
         );
     }
+
+    #[test]
+    fn it_parses_algorithms() {
+        for algorithm in Algorithm::ALL {
+            let name = serde_json::to_value(algorithm).unwrap();
+            assert_eq!(Ok(*algorithm), name.as_str().unwrap().parse());
+        }
+        assert!("none".parse::<Algorithm>().is_err());
+    }
+
+    #[test]
+    fn it_cannot_encode_asymmetric_algorithms() {
+        assert_eq!(None, Algorithm::RsaSha256.encode(b"hello world", b"secret"));
+        assert_eq!(
+            None,
+            Algorithm::EcdsaSha256.encode(b"hello world", b"secret")
+        );
+        assert_eq!(None, Algorithm::EdDsa.encode(b"hello world", b"secret"));
+    }
+
+    #[test]
+    fn it_should_not_verify_hmac_with_a_public_key() {
+        let key = PublicKeyData::Ed25519(b"secret".to_vec());
+        let signature = "c0zGLzKEFWj0VxWuufTXiRMk5tlI5MbGDAYhzaxIYjo";
+
+        assert!(!Algorithm::HmacSha256.verify(
+            b"hello world",
+            signature,
+            VerifyingKey::Public(&key)
+        ));
+        assert!(Algorithm::HmacSha256.verify(
+            b"hello world",
+            signature,
+            VerifyingKey::Secret(b"secret")
+        ));
+    }
 }
@@ -21,19 +21,43 @@ pub fn encode(input: &[u8]) -> String {
     base64
 }
 
+/// Decodes URL-safe Base64 with optional padding
+///
+/// Returns `None` for invalid characters or padding and for non-canonical trailing bits.
 pub fn decode(input: &str) -> Option<Vec<u8>> {
-    let mut vec = input.chars().map(decode_char).collect::<Option<Vec<_>>>()?;
+    let unpadded = input.trim_end_matches('=');
+    let padding = input.len() - unpadded.len();
+    if unpadded.len() % 4 == 1 {
+        return None;
+    }
+    if padding > 0 && (padding > 2 || input.len() % 4 > 0) {
+        return None;
+    }
+
+    let mut vec = unpadded
+        .chars()
+        .map(decode_char)
+        .collect::<Option<Vec<_>>>()?;
+    let len = vec.len() * 6 / 8;
+
+    // The unused bits of the last character have to be zero
+    let unused_bits = match vec.len() % 4 {
+        2 => 0b1111,
+        3 => 0b11,
+        _ => 0,
+    };
+    if vec.last().map_or(false, |&last| last & unused_bits != 0) {
+        return None;
+    }
+
     while vec.len() % 4 > 0 {
         vec.push(0);
     }
 
-    let str = vec
-        .chunks(4)
-        .flat_map(decode_chunk)
-        .take_while(|&c| c != 0)
-        .collect::<Vec<_>>();
+    let mut bytes = vec.chunks(4).flat_map(decode_chunk).collect::<Vec<_>>();
+    bytes.truncate(len);
 
-    Some(str)
+    Some(bytes)
 }
 
 fn encode_chunk(chunk: &[u8]) -> [u8; 4] {
@@ -80,7 +104,6 @@ fn decode_char(char: char) -> Option<u8> {
         '0'..='9' => Some(char as u8 - b'0' + 26 + 26),
         '-' => Some(62),
         '_' => Some(63),
-        '=' => Some(0),
         _ => None,
     }
 }
@@ -111,12 +134,12 @@ mod tests {
         assert_eq!(Some(61), decode_char('9'));
         assert_eq!(Some(62), decode_char('-'));
         assert_eq!(Some(63), decode_char('_'));
-        assert_eq!(Some(0), decode_char('='));
     }
 
     #[test]
     fn it_should_return_none_for_bad_char() {
         assert_eq!(None, decode_char('?'));
+        assert_eq!(None, decode_char('='));
     }
 
     #[test]
@@ -140,4 +163,61 @@ mod tests {
             decode("TG9yZW0gaXBzdW0sIGRvbG9yIHNpdCBhbWV0Lg==")
         );
     }
+
+    #[test]
+    fn it_should_decode_zero_bytes() {
+        assert_eq!(Some(vec![0, 1, 0]), decode("AAEA"));
+        assert_eq!(Some(vec![1, 0]), decode("AQA"));
+        assert_eq!(Some(vec![0]), decode("AA=="));
+    }
+
+    #[test]
+    fn it_should_reject_invalid_padding() {
+        assert_eq!(None, decode("A"));
+        assert_eq!(None, decode("AA="));
+        assert_eq!(None, decode("AA==="));
+        assert_eq!(None, decode("A==="));
+        assert_eq!(None, decode("YW=Jj"));
+        assert_eq!(None, decode("YWJj===="));
+    }
+
+    #[test]
+    fn it_should_reject_non_canonical_trailing_bits() {
+        assert_eq!(Some(vec![0]), decode("AA"));
+        assert_eq!(None, decode("AB"));
+        assert_eq!(None, decode("AAB"));
+    }
+
+    mod properties {
+        use super::super::*;
+        use proptest::prelude::*;
+
+        proptest! {
+            #[test]
+            fn it_should_decode_what_it_encodes(bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+                prop_assert_eq!(Some(bytes.clone()), decode(&encode(&bytes)));
+            }
+
+            #[test]
+            fn it_should_decode_padded_input(bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+                let mut encoded = encode(&bytes);
+                while encoded.len() % 4 > 0 {
+                    encoded.push('=');
+                }
+                prop_assert_eq!(Some(bytes), decode(&encoded));
+            }
+
+            #[test]
+            fn it_should_not_panic_on_any_input(input in "\\PC*") {
+                decode(&input);
+            }
+
+            #[test]
+            fn it_should_only_decode_canonical_input(input in "[A-Za-z0-9_=-]{0,64}") {
+                if let Some(bytes) = decode(&input) {
+                    prop_assert_eq!(input.trim_end_matches('='), encode(&bytes));
+                }
+            }
+        }
+    }
 }
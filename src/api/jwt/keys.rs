@@ -0,0 +1,150 @@
+use super::{JwtError, Result};
+
+/// A secret which signs tokens, identified by the `kid` header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningKey {
+    pub id: String,
+    pub secret: Vec<u8>,
+}
+
+impl SigningKey {
+    pub fn new(id: impl Into<String>, secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            id: id.into(),
+            secret: secret.into(),
+        }
+    }
+
+    /// Parses a key in the format `kid=secret`
+    pub fn parse(entry: &str) -> Result<Self> {
+        let (id, secret) = entry.trim().split_once('=').ok_or(JwtError::Key)?;
+        if id.is_empty() || secret.is_empty() {
+            return Err(JwtError::Key);
+        }
+        Ok(Self::new(id, secret))
+    }
+}
+
+/// A public key of an identity provider, which can only verify tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    pub id: String,
+    pub data: PublicKeyData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicKeyData {
+    /// Modulus and exponent of an RSA key
+    Rsa {
+        n: Vec<u8>,
+        e: Vec<u8>,
+    },
+    /// Uncompressed point of a P-256 key
+    Ecdsa(Vec<u8>),
+    Ed25519(Vec<u8>),
+}
+
+/// A key which verifies the signature of a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyingKey<'a> {
+    Secret(&'a [u8]),
+    Public(&'a PublicKeyData),
+}
+
+/// The accepted keys, of which the first signing key signs new tokens
+///
+/// Retired keys stay in the set until all tokens signed by them are expired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySet {
+    keys: Vec<SigningKey>,
+    public_keys: Vec<PublicKey>,
+}
+
+impl KeySet {
+    pub fn new(keys: Vec<SigningKey>, public_keys: Vec<PublicKey>) -> Result<Self> {
+        if keys.is_empty() && public_keys.is_empty() {
+            return Err(JwtError::Key);
+        }
+        Ok(Self { keys, public_keys })
+    }
+
+    /// Returns the key which signs new tokens, if the engine may issue them
+    pub fn current(&self) -> Option<&SigningKey> {
+        self.keys.first()
+    }
+
+    /// Returns the key with the given id, or the current key for tokens without `kid`
+    pub fn find(&self, id: Option<&str>) -> Option<VerifyingKey<'_>> {
+        let Some(id) = id else {
+            return self.current().map(|key| VerifyingKey::Secret(&key.secret));
+        };
+
+        if let Some(key) = self.keys.iter().find(|key| key.id == id) {
+            return Some(VerifyingKey::Secret(&key.secret));
+        }
+        self.public_keys
+            .iter()
+            .find(|key| key.id == id)
+            .map(|key| VerifyingKey::Public(&key.data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_a_key() {
+        assert_eq!(
+            Ok(SigningKey::new("2024", "a=b")),
+            SigningKey::parse("2024=a=b")
+        );
+        assert_eq!(Err(JwtError::Key), SigningKey::parse("secret"));
+        assert_eq!(Err(JwtError::Key), SigningKey::parse("2024="));
+    }
+
+    #[test]
+    fn it_should_find_keys_by_id() {
+        let public_key = PublicKey {
+            id: "idp".into(),
+            data: PublicKeyData::Ed25519(vec![1; 32]),
+        };
+        let keys = KeySet::new(
+            vec![
+                SigningKey::new("new", "secret"),
+                SigningKey::new("old", "retired"),
+            ],
+            vec![public_key.clone()],
+        )
+        .unwrap();
+
+        assert_eq!(Some("new"), keys.current().map(|key| key.id.as_str()));
+        assert_eq!(
+            Some(VerifyingKey::Secret(b"retired")),
+            keys.find(Some("old"))
+        );
+        assert_eq!(Some(VerifyingKey::Secret(b"secret")), keys.find(None));
+        assert_eq!(
+            Some(VerifyingKey::Public(&public_key.data)),
+            keys.find(Some("idp"))
+        );
+        assert_eq!(None, keys.find(Some("unknown")));
+    }
+
+    #[test]
+    fn it_should_not_sign_with_public_keys_only() {
+        let public_key = PublicKey {
+            id: "idp".into(),
+            data: PublicKeyData::Ed25519(vec![1; 32]),
+        };
+        let keys = KeySet::new(Vec::new(), vec![public_key]).unwrap();
+
+        assert_eq!(None, keys.current());
+        assert_eq!(None, keys.find(None));
+    }
+
+    #[test]
+    fn it_should_require_a_key() {
+        assert_eq!(Err(JwtError::Key), KeySet::new(Vec::new(), Vec::new()));
+    }
+}
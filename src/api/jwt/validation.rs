@@ -0,0 +1,109 @@
+use super::{Jwt, JwtError, Result};
+
+/// Validates the registered claims of a token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validation {
+    /// Current time in seconds since the epoch
+    pub now: u64,
+    /// Allowed clock skew in seconds
+    pub leeway: u64,
+    /// Expected issuer, or `None` to accept any
+    pub issuer: Option<String>,
+    /// Expected audience, or `None` to accept any
+    pub audience: Option<String>,
+}
+
+impl Validation {
+    pub fn validate(&self, jwt: &Jwt) -> Result<()> {
+        let payload = &jwt.payload;
+
+        let expires_at = payload.expires_at.ok_or(JwtError::Claim)?;
+        if self.now >= expires_at.saturating_add(self.leeway) {
+            return Err(JwtError::Expired);
+        }
+        if let Some(not_before) = payload.not_before {
+            if self.now.saturating_add(self.leeway) < not_before {
+                return Err(JwtError::NotYetValid);
+            }
+        }
+        if let Some(issued_at) = payload.issued_at {
+            if self.now.saturating_add(self.leeway) < issued_at {
+                return Err(JwtError::NotYetValid);
+            }
+        }
+
+        if let Some(issuer) = &self.issuer {
+            if payload.issuer.as_ref() != Some(issuer) {
+                return Err(JwtError::Claim);
+            }
+        }
+        if let Some(audience) = &self.audience {
+            let matches = payload
+                .audience
+                .as_ref()
+                .map_or(false, |aud| aud.contains(audience));
+            if !matches {
+                return Err(JwtError::Claim);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::jwt::Algorithm;
+
+    fn validation(now: u64) -> Validation {
+        Validation {
+            now,
+            leeway: 10,
+            issuer: Some("engine".into()),
+            audience: Some("traders".into()),
+        }
+    }
+
+    fn token() -> Jwt {
+        Jwt::new(Algorithm::HmacSha256, "1".into())
+            .with_lifetime(1_000, 60)
+            .with_issuer(Some("engine".into()))
+            .with_audience(Some("traders".into()))
+    }
+
+    #[test]
+    fn it_should_accept_a_valid_token() {
+        assert_eq!(Ok(()), validation(1_000).validate(&token()));
+        assert_eq!(Ok(()), validation(1_069).validate(&token()));
+    }
+
+    #[test]
+    fn it_should_reject_an_expired_token() {
+        assert_eq!(Err(JwtError::Expired), validation(1_070).validate(&token()));
+    }
+
+    #[test]
+    fn it_should_reject_a_token_which_is_not_yet_valid() {
+        assert_eq!(Ok(()), validation(990).validate(&token()));
+        assert_eq!(
+            Err(JwtError::NotYetValid),
+            validation(989).validate(&token())
+        );
+    }
+
+    #[test]
+    fn it_should_reject_a_token_without_expiry() {
+        let jwt = Jwt::new(Algorithm::HmacSha256, "1".into());
+        assert_eq!(Err(JwtError::Claim), validation(1_000).validate(&jwt));
+    }
+
+    #[test]
+    fn it_should_reject_a_foreign_issuer_or_audience() {
+        let jwt = token().with_issuer(Some("other".into()));
+        assert_eq!(Err(JwtError::Claim), validation(1_000).validate(&jwt));
+
+        let jwt = token().with_audience(None);
+        assert_eq!(Err(JwtError::Claim), validation(1_000).validate(&jwt));
+    }
+}
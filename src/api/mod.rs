@@ -1,33 +1,48 @@
+mod account;
+mod admin;
+mod api_key;
 mod buckets;
 mod context;
 mod disconnect;
 mod error;
 mod jwt;
+mod rate_limit;
+mod session;
 
 use std::convert::Infallible;
 use std::io::Write;
+use std::net::SocketAddr;
 use std::ops::Deref;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use futures::StreamExt;
-use hyper::header::{ALLOW, AUTHORIZATION, CONTENT_TYPE};
+use futures::{future, StreamExt};
+use hyper::header::{ALLOW, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
 use hyper::http::HeaderValue;
 use hyper::server::conn::AddrStream;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Method, Request, Response, Server, StatusCode};
 use log::{debug, error, info};
 use prometheus::{Encoder, TextEncoder};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use tokio::signal;
 use tokio::time::Instant;
 
+use self::api_key::{RequestSignature, SignatureError};
 pub use self::context::Context;
 use self::disconnect::with_disconnect_fn;
 use self::error::{to_http_err, HttpResult};
-use crate::api::jwt::{Algorithm, Jwt};
+use self::rate_limit::RouteClass;
+use self::session::{ConnectionSession, Session, CANCEL_ON_DISCONNECT};
+use crate::api::jwt::{Algorithm, Jwt, JwtError, KeySet, Validation};
 use crate::config::Config;
-use crate::model::User;
+use crate::model::{
+    deserialize_query_nanos, BatchItem, BatchResult, BookView, CancelOrders, Interval, OpenOrder,
+    Order, OrderId, Scope, Side, TradePage, User,
+};
 
-const SECRET: &[u8; 16] = b"ThisIsNotSoSeret";
+/// The maximum number of items in a batch
+const MAX_BATCH_SIZE: usize = 100;
 
 pub async fn api(config: Config, context: Context) {
     let Ok(addr) = config.host.parse() else {
@@ -48,12 +63,17 @@ pub async fn api(config: Config, context: Context) {
 
         // Create a `Service` for responding to the request.
         let ctx = context.clone();
-        let service = service_fn(move |req| handle(ctx.clone(), req));
+        let session = ConnectionSession::default();
+        let conn_session = session.clone();
+        let service = service_fn(move |req| handle(ctx.clone(), addr, conn_session.clone(), req));
 
         // Listen for the service being disconnected.
         let dropping = with_disconnect_fn(service, move || {
             debug!("Disconnected {}", addr);
             context.dec_connections();
+            if let Some(session) = session.lock().unwrap().take() {
+                context.close_session(session);
+            }
         });
 
         // Return the service to hyper.
@@ -78,12 +98,17 @@ pub async fn api(config: Config, context: Context) {
 }
 
 /// Handles an incoming request
-async fn handle(context: Context, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+async fn handle(
+    context: Context,
+    addr: SocketAddr,
+    session: ConnectionSession,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
     let method = req.method().clone();
     let uri = req.uri().clone();
 
     let time = Instant::now();
-    let res = handle_routing(&context, req)
+    let res = handle_routing(&context, addr, &session, req)
         .await
         .unwrap_or_else(|err| err.into());
     let elapsed = time.elapsed();
@@ -93,41 +118,168 @@ async fn handle(context: Context, req: Request<Body>) -> Result<Response<Body>,
     Ok(res)
 }
 
-async fn handle_routing(context: &Context, req: Request<Body>) -> HttpResult<Response<Body>> {
+async fn handle_routing(
+    context: &Context,
+    addr: SocketAddr,
+    session: &ConnectionSession,
+    req: Request<Body>,
+) -> HttpResult<Response<Body>> {
+    let class = RouteClass::of(req.method());
+    if let Err(retry_after) = context.acquire_address(class, addr.ip(), unix_millis()) {
+        return too_many_requests(retry_after);
+    }
+
     let authorization = req
         .headers()
         .get(AUTHORIZATION)
         .and_then(|hv| hv.to_str().ok());
     let Some(authorization) = authorization else {
         return match (req.method(), req.uri().path()) {
-            (&Method::POST, "/login") => handle_login(req.into_body()).await,
+            (&Method::POST, "/login") => {
+                account::handle_login(context, addr.ip(), req.into_body()).await
+            }
             (_other_method, "/login") => method_not_allowed(&[Method::POST]),
 
+            (&Method::POST, "/register") => {
+                account::handle_register(context, req.into_body()).await
+            }
+            (_other_method, "/register") => method_not_allowed(&[Method::POST]),
+
             _ => unauthorized(),
         };
     };
 
-    let user = parse_auth_header(authorization).map(extract_user_from_token);
-    let Some(user) = user else {
-        return forbidden();
+    let validation = validation(context.config());
+    let authorization = parse_auth_header(
+        authorization,
+        context.keys(),
+        context.algorithms(),
+        &validation,
+    );
+    let (user, req) = match authorization {
+        Ok(Authorization::Bearer(token)) => (extract_user_from_token(token), req),
+        Ok(Authorization::ApiKey(signature)) => {
+            // The signature covers the body, so it has to be read upfront
+            let (parts, body) = req.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            let path = parts.uri.path_and_query().map_or("/", |path| path.as_str());
+            match api_key::authenticate(context, &signature, &parts.method, path, &body).await {
+                Ok(user) => (user, Request::from_parts(parts, body.into())),
+                Err(SignatureError::Expired | SignatureError::Replayed) => return unauthorized(),
+                Err(_) => return forbidden(),
+            }
+        }
+        Err(JwtError::Expired | JwtError::NotYetValid) => return unauthorized(),
+        Err(_) => return forbidden(),
     };
 
+    if let Err(retry_after) = context.acquire_user(class, user.id(), unix_millis()) {
+        return too_many_requests(retry_after);
+    }
+
+    let scope = if req.method() == Method::GET {
+        Scope::Read
+    } else {
+        Scope::Trade
+    };
+    if !user.allows(scope) {
+        return forbidden();
+    }
+
+    if let Some(value) = req.headers().get(CANCEL_ON_DISCONNECT) {
+        let Some(grace) = session::parse_grace(value) else {
+            return Err(Box::new(error::BadRequest));
+        };
+        if !user.allows(Scope::Trade) {
+            return forbidden();
+        }
+        if !open_session(context, session, &user, grace) {
+            return conflict();
+        }
+    }
+
+    if req.uri().path().starts_with("/admin/") {
+        if !user.is_admin() {
+            return forbidden();
+        }
+        return admin::handle_routing(context, &user, req).await;
+    }
+
+    if req.uri().path().starts_with("/api-keys") {
+        // API keys can not manage other keys
+        if user.is_api_key() {
+            return forbidden();
+        }
+        return match (req.method(), req.uri().path()) {
+            (&Method::POST, "/api-keys") => {
+                api_key::handle_create(context, &user, req.into_body()).await
+            }
+            (&Method::GET, "/api-keys") => api_key::handle_list(context, &user).await,
+            (_other_method, "/api-keys") => method_not_allowed(&[Method::GET, Method::POST]),
+
+            (&Method::DELETE, path) => match path.strip_prefix("/api-keys/") {
+                Some(key) if !key.is_empty() => api_key::handle_revoke(context, &user, key).await,
+                _ => not_found(),
+            },
+            (_other_method, _) => method_not_allowed(&[Method::DELETE]),
+        };
+    }
+
     match (req.method(), req.uri().path()) {
-        (&Method::GET, "/") => handle_get_order_book(context).await,
+        (&Method::GET, "/") => handle_get_order_book(context, &req).await,
         (_other_method, "/") => method_not_allowed(&[Method::GET]),
 
-        (&Method::GET, "/subscribe") => handle_subscribe_order_book(context).await,
+        (&Method::GET, "/subscribe") => handle_subscribe_order_book(context, &req).await,
         (_other_method, "/subscribe") => method_not_allowed(&[Method::GET]),
 
+        (&Method::GET, "/executions") => handle_subscribe_executions(context, &user).await,
+        (_other_method, "/executions") => method_not_allowed(&[Method::GET]),
+
+        (&Method::GET, "/l3") => handle_get_l3(context).await,
+        (_other_method, "/l3") => method_not_allowed(&[Method::GET]),
+
+        (&Method::GET, "/l3/subscribe") => handle_subscribe_l3(context).await,
+        (_other_method, "/l3/subscribe") => method_not_allowed(&[Method::GET]),
+
+        (&Method::GET, "/candles") => handle_get_candles(context, &req).await,
+        (_other_method, "/candles") => method_not_allowed(&[Method::GET]),
+
+        (&Method::GET, "/candles/subscribe") => handle_subscribe_candles(context, &req).await,
+        (_other_method, "/candles/subscribe") => method_not_allowed(&[Method::GET]),
+
+        (&Method::GET, "/ticker") => handle_get_ticker(context).await,
+        (_other_method, "/ticker") => method_not_allowed(&[Method::GET]),
+
         (&Method::GET, "/me") => handle_get_me(context, &user).await,
         (_other_method, "/me") => method_not_allowed(&[Method::GET]),
 
-        (&Method::GET, "/trades") => handle_get_trades(context).await,
+        (&Method::GET, "/trades") => handle_get_trades(context, &req).await,
         (_other_method, "/trades") => method_not_allowed(&[Method::GET]),
 
-        (&Method::POST, "/orders") => handle_open_order(context, req.into_body()).await,
+        (&Method::GET, "/trades/subscribe") => handle_subscribe_trades(context, &req).await,
+        (_other_method, "/trades/subscribe") => method_not_allowed(&[Method::GET]),
+
+        (&Method::POST, "/orders") => handle_open_order(context, &user, req.into_body()).await,
         (_other_method, "/orders") => method_not_allowed(&[Method::POST]),
 
+        (&Method::POST, "/orders/batch") => handle_batch(context, &user, req.into_body()).await,
+        (_other_method, "/orders/batch") => method_not_allowed(&[Method::POST]),
+
+        (&Method::POST, "/orders/cancel") => {
+            handle_cancel_orders(context, &user, req.into_body()).await
+        }
+        (_other_method, "/orders/cancel") => method_not_allowed(&[Method::POST]),
+
+        (method, path) if path.starts_with("/orders/client/") => {
+            match (method, path.trim_start_matches("/orders/client/")) {
+                (_method, "") => not_found(),
+                (&Method::GET, client_order_id) => {
+                    handle_get_client_order(context, &user, client_order_id).await
+                }
+                (_other_method, _) => method_not_allowed(&[Method::GET]),
+            }
+        }
+
         (&Method::GET, "/metrics") => handle_metrics(context),
         (_other_method, "/metrics") => method_not_allowed(&[Method::GET]),
 
@@ -135,43 +287,120 @@ async fn handle_routing(context: &Context, req: Request<Body>) -> HttpResult<Res
     }
 }
 
-pub fn parse_auth_header(str: &str) -> Option<Jwt> {
+/// Opts the connection in to cancel-on-disconnect, which fails if another user did so
+fn open_session(
+    context: &Context,
+    session: &ConnectionSession,
+    user: &User,
+    grace: Duration,
+) -> bool {
+    let mut session = session.lock().unwrap();
+    match session.as_mut() {
+        Some(session) if session.user_id != user.id() => return false,
+        Some(session) => session.grace = grace,
+        None => {
+            context.open_session(user.id());
+            *session = Some(Session {
+                user_id: user.id().to_string(),
+                grace,
+            });
+        }
+    }
+    true
+}
+
+/// The credentials of a request
+pub enum Authorization {
+    /// A valid token of a login
+    Bearer(Jwt),
+    /// The signature of an API key, which is verified against the request
+    ApiKey(RequestSignature),
+}
+
+pub fn parse_auth_header(
+    str: &str,
+    keys: &KeySet,
+    algorithms: &[Algorithm],
+    validation: &Validation,
+) -> Result<Authorization, JwtError> {
+    if let Some(str) = str.strip_prefix(api_key::SCHEME) {
+        let signature = RequestSignature::parse(str).ok_or(JwtError::Malformed)?;
+        return Ok(Authorization::ApiKey(signature));
+    }
+
     if !str.starts_with("Bearer ") {
-        return None;
+        return Err(JwtError::Malformed);
     }
 
     let str = &str["Bearer ".len()..];
-    Jwt::decode(str, SECRET).ok()
+    let token = Jwt::decode_with_keys(str, keys, algorithms)?;
+    validation.validate(&token)?;
+    Ok(Authorization::Bearer(token))
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct LoginPayload {
-    pub user_id: String,
+/// Returns the validation of tokens according to the config
+fn validation(config: &Config) -> Validation {
+    Validation {
+        now: unix_time(),
+        leeway: config.token_leeway,
+        issuer: config.token_issuer.clone(),
+        audience: config.token_audience.clone(),
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
-struct LoginResponse {
-    token: String,
+/// Returns the seconds since the epoch
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
 }
 
-async fn handle_login(req: Body) -> HttpResult<Response<Body>> {
-    let payload = json_request::<LoginPayload>(req).await?;
-    let token = Jwt::new(Algorithm::HmacSha256, payload.user_id).encode(SECRET)?;
-    let res = json_response(StatusCode::OK, &LoginResponse { token })?;
-    Ok(res)
+/// Returns the milliseconds since the epoch
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// Reads the options of a view of the order book from the query
+fn book_view(req: &Request<Body>) -> HttpResult<BookView> {
+    let view = query_request::<BookView>(req)?;
+    if view.validate().is_err() {
+        return Err(Box::new(error::BadRequest));
+    }
+    Ok(view)
 }
 
-async fn handle_get_order_book(context: &Context) -> HttpResult<Response<Body>> {
+async fn handle_get_order_book(
+    context: &Context,
+    req: &Request<Body>,
+) -> HttpResult<Response<Body>> {
+    let view = book_view(req)?;
     let order_book = context.read_order_book().await;
-    let res = json_response(StatusCode::OK, &order_book.deref())?;
+    let res = json_response(StatusCode::OK, &view.apply(order_book.deref()))?;
     Ok(res)
 }
 
-async fn handle_subscribe_order_book(context: &Context) -> HttpResult<Response<Body>> {
+/// Streams the order book, skipping changes which are not visible in the view
+async fn handle_subscribe_order_book(
+    context: &Context,
+    req: &Request<Body>,
+) -> HttpResult<Response<Body>> {
+    let view = book_view(req)?;
+    let mut last = None;
     let body = Body::wrap_stream(
         context
             .subscribe_order_book()
+            .map(move |ob| view.apply(&ob))
+            .filter(move |ob| {
+                let changed = last.as_ref() != Some(ob);
+                if changed {
+                    last = Some(ob.clone());
+                }
+                future::ready(changed)
+            })
             .map(|ob| serde_json::to_string(&ob).unwrap())
             .map(Result::<_, Infallible>::Ok),
     );
@@ -179,29 +408,331 @@ async fn handle_subscribe_order_book(context: &Context) -> HttpResult<Response<B
     Ok(res)
 }
 
+/// Streams the execution reports of the orders of the user
+async fn handle_subscribe_executions(context: &Context, user: &User) -> HttpResult<Response<Body>> {
+    let body = Body::wrap_stream(
+        context
+            .subscribe_execution_reports(user.id().to_string())
+            .map(|report| serde_json::to_string(&report).unwrap())
+            .map(Result::<_, Infallible>::Ok),
+    );
+    let res = Response::new(body);
+    Ok(res)
+}
+
+async fn handle_get_l3(context: &Context) -> HttpResult<Response<Body>> {
+    let snapshot = context.read_l3().await?;
+    let res = json_response(StatusCode::OK, &snapshot)?;
+    Ok(res)
+}
+
+async fn handle_subscribe_l3(context: &Context) -> HttpResult<Response<Body>> {
+    let body = Body::wrap_stream(
+        context
+            .subscribe_l3()
+            .map(|event| serde_json::to_string(&event).unwrap())
+            .map(Result::<_, Infallible>::Ok),
+    );
+    let res = Response::new(body);
+    Ok(res)
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+struct CandlesQuery {
+    interval: Interval,
+    /// Nanoseconds since the epoch, which include the candle containing them
+    #[serde(default, deserialize_with = "deserialize_query_nanos")]
+    from: Option<u128>,
+    #[serde(default, deserialize_with = "deserialize_query_nanos")]
+    to: Option<u128>,
+}
+
+async fn handle_get_candles(context: &Context, req: &Request<Body>) -> HttpResult<Response<Body>> {
+    let query = query_request::<CandlesQuery>(req)?;
+    let candles = context
+        .read_candles(query.interval, query.from, query.to)
+        .await;
+    let res = json_response(StatusCode::OK, &candles)?;
+    Ok(res)
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+struct SubscribeCandlesQuery {
+    interval: Interval,
+}
+
+/// Streams the current candle of an interval whenever it changes
+async fn handle_subscribe_candles(
+    context: &Context,
+    req: &Request<Body>,
+) -> HttpResult<Response<Body>> {
+    let query = query_request::<SubscribeCandlesQuery>(req)?;
+    let body = Body::wrap_stream(
+        context
+            .subscribe_candles(query.interval)
+            .map(|candle| serde_json::to_string(&candle).unwrap())
+            .map(Result::<_, Infallible>::Ok),
+    );
+    let res = Response::new(body);
+    Ok(res)
+}
+
+async fn handle_get_ticker(context: &Context) -> HttpResult<Response<Body>> {
+    let ticker = context.read_ticker().await;
+    let res = json_response(StatusCode::OK, &ticker)?;
+    Ok(res)
+}
+
 async fn handle_get_me(_context: &Context, user: &User) -> HttpResult<Response<Body>> {
     let res = json_response(StatusCode::OK, user)?;
     Ok(res)
 }
 
-async fn handle_get_trades(context: &Context) -> HttpResult<Response<Body>> {
-    let trades = context.read_trades().await;
-    let res = json_response(StatusCode::OK, &trades.deref())?;
+async fn handle_get_trades(context: &Context, req: &Request<Body>) -> HttpResult<Response<Body>> {
+    let page = query_request::<TradePage>(req)?;
+    if page.validate().is_err() {
+        return Err(Box::new(error::BadRequest));
+    }
+    let trades = context.read_trades(&page).await?;
+    let res = json_response(StatusCode::OK, &trades)?;
     Ok(res)
 }
 
-async fn handle_open_order(context: &Context, req: Body) -> HttpResult<Response<Body>> {
-    let order = json_request(req).await?;
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+struct SubscribeTradesQuery {
+    /// The sequence number of the last received trade to resume after
+    #[serde(default)]
+    after: Option<usize>,
+}
+
+/// Streams the trades as they are made, optionally starting with the ones after a sequence number
+async fn handle_subscribe_trades(
+    context: &Context,
+    req: &Request<Body>,
+) -> HttpResult<Response<Body>> {
+    let query = query_request::<SubscribeTradesQuery>(req)?;
+    // Resuming after a sequence which does not exist yet starts with the next trade
+    let count = context.trade_count().await;
+    let after = query.after.map_or(count, |after| after.min(count));
+    let amended = context.amendment_count().await;
+
+    let body = Body::wrap_stream(
+        context
+            .subscribe_trades(after, amended)
+            .map(|event| event.map(|event| serde_json::to_string(&event).unwrap())),
+    );
+    let res = Response::new(body);
+    Ok(res)
+}
+
+async fn handle_open_order(
+    context: &Context,
+    user: &User,
+    req: Body,
+) -> HttpResult<Response<Body>> {
+    let mut order = json_request::<OpenOrder>(req).await?;
+    if order.validate().is_err() {
+        return Err(Box::new(error::BadRequest));
+    }
+    order.user_id = user.id().to_string();
+
+    // A resubmission returns the original order, which the matcher also ensures
+    if let Some(client_order_id) = &order.client_order_id {
+        if let Some(order) = context.read_client_order(user.id(), client_order_id).await {
+            let res = json_response(StatusCode::OK, &order)?;
+            return Ok(res);
+        }
+    }
+
     let order = context.open_order(order).await?;
     let res = json_response(StatusCode::CREATED, &order)?;
     Ok(res)
 }
 
+async fn handle_get_client_order(
+    context: &Context,
+    user: &User,
+    client_order_id: &str,
+) -> HttpResult<Response<Body>> {
+    let Some(order) = context.read_client_order(user.id(), client_order_id).await else {
+        return not_found();
+    };
+    let res = json_response(StatusCode::OK, &order)?;
+    Ok(res)
+}
+
+/// A filter of the resting orders to cancel
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct CancelPayload {
+    #[serde(default)]
+    instrument: Option<String>,
+    #[serde(default)]
+    order_id: Option<OrderId>,
+    #[serde(default)]
+    client_order_id: Option<String>,
+    #[serde(default)]
+    side: Option<Side>,
+    #[serde(default)]
+    min_price: Option<Decimal>,
+    #[serde(default)]
+    max_price: Option<Decimal>,
+}
+
+impl CancelPayload {
+    /// Returns whether the filter is for another instrument than the traded one
+    fn is_other_instrument(&self, config: &Config) -> bool {
+        self.instrument
+            .as_ref()
+            .map_or(false, |instrument| instrument != &config.instrument)
+    }
+
+    fn into_command(self, user_id: Option<String>) -> CancelOrders {
+        CancelOrders {
+            user_id,
+            order_id: self.order_id,
+            client_order_id: self.client_order_id,
+            side: self.side,
+            min_price: self.min_price,
+            max_price: self.max_price,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct CancelResponse {
+    order_ids: Vec<OrderId>,
+}
+
+/// Cancels the resting orders of the user which match the filter
+async fn handle_cancel_orders(
+    context: &Context,
+    user: &User,
+    req: Body,
+) -> HttpResult<Response<Body>> {
+    let payload = json_request::<CancelPayload>(req).await?;
+    if payload.is_other_instrument(context.config()) {
+        return not_found();
+    }
+
+    let command = payload.into_command(Some(user.id().to_string()));
+    let order_ids = context.cancel_orders(command).await?;
+    let res = json_response(StatusCode::OK, &CancelResponse { order_ids })?;
+    Ok(res)
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct BatchPayload {
+    items: Vec<BatchItemPayload>,
+    /// Whether no item is processed if any of them is invalid
+    #[serde(default)]
+    all_or_nothing: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum BatchItemPayload {
+    Open(OpenOrder),
+    Cancel(CancelPayload),
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+enum BatchItemResponse {
+    Open(Order),
+    Cancel(CancelResponse),
+    Invalid(&'static str),
+    /// The item is valid, but another one of an all-or-nothing batch is not
+    Skipped,
+}
+
+impl From<BatchResult> for BatchItemResponse {
+    fn from(result: BatchResult) -> Self {
+        match result {
+            BatchResult::Open(order) => BatchItemResponse::Open(order),
+            BatchResult::Cancel(order_ids) => {
+                BatchItemResponse::Cancel(CancelResponse { order_ids })
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct BatchResponse {
+    results: Vec<BatchItemResponse>,
+}
+
+/// Opens and cancels orders of the user in one step of the matcher
+async fn handle_batch(context: &Context, user: &User, req: Body) -> HttpResult<Response<Body>> {
+    let payload = json_request::<BatchPayload>(req).await?;
+    if payload.items.is_empty() || payload.items.len() > MAX_BATCH_SIZE {
+        return Err(Box::new(error::BadRequest));
+    }
+
+    let items = payload
+        .items
+        .into_iter()
+        .map(|item| match item {
+            BatchItemPayload::Open(mut order) => {
+                order.validate()?;
+                order.user_id = user.id().to_string();
+                Ok(BatchItem::Open(order))
+            }
+            BatchItemPayload::Cancel(filter) => {
+                if filter.is_other_instrument(context.config()) {
+                    return Err("unknown instrument");
+                }
+                Ok(BatchItem::Cancel(
+                    filter.into_command(Some(user.id().to_string())),
+                ))
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if payload.all_or_nothing && items.iter().any(Result::is_err) {
+        let results = items
+            .into_iter()
+            .map(|item| match item {
+                Ok(_) => BatchItemResponse::Skipped,
+                Err(reason) => BatchItemResponse::Invalid(reason),
+            })
+            .collect();
+        let res = json_response(StatusCode::BAD_REQUEST, &BatchResponse { results })?;
+        return Ok(res);
+    }
+
+    let mut batch = Vec::new();
+    let mut results = Vec::new();
+    for item in items {
+        match item {
+            Ok(item) => {
+                batch.push(item);
+                results.push(None);
+            }
+            Err(reason) => results.push(Some(BatchItemResponse::Invalid(reason))),
+        }
+    }
+
+    let processed = match batch.is_empty() {
+        true => Vec::new(),
+        false => context.process_batch(batch).await?,
+    };
+    let mut processed = processed.into_iter().map(BatchItemResponse::from);
+    let results = results
+        .into_iter()
+        .filter_map(|result| result.or_else(|| processed.next()))
+        .collect();
+    let res = json_response(StatusCode::OK, &BatchResponse { results })?;
+    Ok(res)
+}
+
 async fn json_request<T: for<'a> Deserialize<'a>>(req: Body) -> HttpResult<T> {
     let str = hyper::body::to_bytes(req).await?;
     serde_json::from_slice::<T>(&str).map_err(to_http_err(error::BadRequest))
 }
 
+fn query_request<T: for<'a> Deserialize<'a>>(req: &Request<Body>) -> HttpResult<T> {
+    let query = req.uri().query().unwrap_or_default();
+    serde_urlencoded::from_str::<T>(query).map_err(to_http_err(error::BadRequest))
+}
+
 fn json_response<T: Serialize>(status: StatusCode, data: &T) -> HttpResult<Response<Body>> {
     let json = serde_json::to_string(data)?;
     let mut res = Response::new(json.into());
@@ -255,6 +786,21 @@ fn unauthorized() -> HttpResult<Response<Body>> {
     Ok(res)
 }
 
+/// Return a 409 Conflict response
+fn conflict() -> HttpResult<Response<Body>> {
+    let mut res = Response::default();
+    *res.status_mut() = StatusCode::CONFLICT;
+    Ok(res)
+}
+
+/// Return a 429 Too Many Requests response, which may be retried after the given seconds
+fn too_many_requests(retry_after: u64) -> HttpResult<Response<Body>> {
+    let mut res = Response::default();
+    *res.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+    res.headers_mut().insert(RETRY_AFTER, retry_after.into());
+    Ok(res)
+}
+
 /// Return a 403 Forbidden response
 fn forbidden() -> HttpResult<Response<Body>> {
     let mut res = Response::default();
@@ -263,5 +809,5 @@ fn forbidden() -> HttpResult<Response<Body>> {
 }
 
 pub fn extract_user_from_token(token: Jwt) -> User {
-    User::new(token.payload.subject)
+    User::new(token.payload.subject, token.payload.roles)
 }
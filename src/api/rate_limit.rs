@@ -0,0 +1,221 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::Hash;
+use std::net::IpAddr;
+
+use hyper::Method;
+
+use crate::config::{Config, RateLimit};
+
+/// Buckets of a limiter, after which only a full one makes room for another key
+const MAX_BUCKETS: usize = 10_000;
+
+/// A group of routes which share a rate limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteClass {
+    /// Orders and all other requests which change the engine
+    Orders,
+    Reads,
+}
+
+impl RouteClass {
+    pub fn of(method: &Method) -> Self {
+        match *method {
+            Method::GET | Method::HEAD => RouteClass::Reads,
+            _ => RouteClass::Orders,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RouteClass::Orders => "orders",
+            RouteClass::Reads => "reads",
+        }
+    }
+}
+
+/// The rate limits of each route class per user and per remote address
+#[derive(Debug)]
+pub struct RateLimits {
+    user_orders: RateLimiter<String>,
+    user_reads: RateLimiter<String>,
+    address_orders: RateLimiter<IpAddr>,
+    address_reads: RateLimiter<IpAddr>,
+}
+
+impl RateLimits {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            user_orders: RateLimiter::new(config.order_rate_limit),
+            user_reads: RateLimiter::new(config.read_rate_limit),
+            address_orders: RateLimiter::new(config.address_order_rate_limit),
+            address_reads: RateLimiter::new(config.address_read_rate_limit),
+        }
+    }
+
+    /// Takes a token of the user, or returns the seconds until one is available
+    pub fn acquire_user(&mut self, class: RouteClass, user_id: &str, now: u64) -> Result<(), u64> {
+        match class {
+            RouteClass::Orders => self.user_orders.acquire(user_id, now),
+            RouteClass::Reads => self.user_reads.acquire(user_id, now),
+        }
+    }
+
+    /// Takes a token of the address, or returns the seconds until one is available
+    pub fn acquire_address(
+        &mut self,
+        class: RouteClass,
+        address: IpAddr,
+        now: u64,
+    ) -> Result<(), u64> {
+        match class {
+            RouteClass::Orders => self.address_orders.acquire(&address, now),
+            RouteClass::Reads => self.address_reads.acquire(&address, now),
+        }
+    }
+}
+
+/// Token buckets of the same limit by their key
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    limit: RateLimit,
+    capacity: usize,
+    buckets: HashMap<K, Bucket>,
+    /// Keys by the time their bucket is full again, so it can be removed without losing a limit
+    refills: BTreeSet<(u64, K)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    /// Milliseconds since the epoch
+    updated_at: u64,
+}
+
+impl<K: Ord + Hash + Clone> RateLimiter<K> {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            capacity: MAX_BUCKETS,
+            buckets: HashMap::new(),
+            refills: BTreeSet::new(),
+        }
+    }
+
+    /// Takes a token of the key, or returns the seconds until one is available
+    ///
+    /// A new key is refused while there is no room and no bucket is full.
+    pub fn acquire<Q>(&mut self, key: &Q, now: u64) -> Result<(), u64>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ToOwned<Owned = K> + ?Sized,
+    {
+        let RateLimit { rate, burst } = self.limit;
+        let burst = f64::from(burst);
+        if self.buckets.len() >= self.capacity && !self.buckets.contains_key(key) {
+            self.remove_full(now)?;
+        }
+
+        let key = key.to_owned();
+        let bucket = self.buckets.entry(key.clone()).or_insert(Bucket {
+            tokens: burst,
+            updated_at: now,
+        });
+        self.refills
+            .remove(&(bucket.full_at(rate, burst), key.clone()));
+        bucket.tokens = bucket.refill(rate, burst, now);
+        bucket.updated_at = now;
+
+        let acquired = match bucket.tokens >= 1.0 {
+            true => {
+                bucket.tokens -= 1.0;
+                Ok(())
+            }
+            false => {
+                let retry_after = ((1.0 - bucket.tokens) / rate).ceil() as u64;
+                Err(u64::max(1, retry_after))
+            }
+        };
+        self.refills.insert((bucket.full_at(rate, burst), key));
+        acquired
+    }
+
+    /// Removes the bucket which is full the longest, or returns the seconds until one is full
+    fn remove_full(&mut self, now: u64) -> Result<(), u64> {
+        let Some((full_at, key)) = self.refills.iter().next().cloned() else {
+            return Ok(());
+        };
+        if full_at > now {
+            return Err(u64::max(1, (full_at - now + 999) / 1000));
+        }
+
+        self.refills.remove(&(full_at, key.clone()));
+        self.buckets.remove(&key);
+        Ok(())
+    }
+}
+
+impl Bucket {
+    /// Returns the tokens after refilling them until now
+    fn refill(&self, rate: f64, burst: f64, now: u64) -> f64 {
+        let elapsed = now.saturating_sub(self.updated_at) as f64 / 1000.0;
+        f64::min(burst, self.tokens + elapsed * rate)
+    }
+
+    /// Returns the milliseconds since the epoch when the bucket is full again
+    fn full_at(&self, rate: f64, burst: f64) -> u64 {
+        self.updated_at + ((burst - self.tokens) / rate * 1000.0).ceil() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_classify_routes() {
+        assert_eq!(RouteClass::Reads, RouteClass::of(&Method::GET));
+        assert_eq!(RouteClass::Orders, RouteClass::of(&Method::POST));
+        assert_eq!(RouteClass::Orders, RouteClass::of(&Method::DELETE));
+    }
+
+    #[test]
+    fn it_should_allow_a_burst() {
+        let mut limiter = RateLimiter::<String>::new(RateLimit::new(1.0, 3));
+
+        assert_eq!(Ok(()), limiter.acquire("alice", 0));
+        assert_eq!(Ok(()), limiter.acquire("alice", 0));
+        assert_eq!(Ok(()), limiter.acquire("alice", 0));
+        assert_eq!(Err(1), limiter.acquire("alice", 0));
+        assert_eq!(Ok(()), limiter.acquire("bob", 0));
+    }
+
+    #[test]
+    fn it_should_refill_tokens() {
+        let mut limiter = RateLimiter::<String>::new(RateLimit::new(0.25, 1));
+
+        assert_eq!(Ok(()), limiter.acquire("alice", 0));
+        assert_eq!(Err(4), limiter.acquire("alice", 0));
+        assert_eq!(Err(2), limiter.acquire("alice", 2_000));
+        assert_eq!(Ok(()), limiter.acquire("alice", 4_000));
+        assert_eq!(Ok(()), limiter.acquire("alice", 60_000));
+        assert_eq!(Err(4), limiter.acquire("alice", 60_000));
+    }
+
+    #[test]
+    fn it_should_only_remove_full_buckets() {
+        let mut limiter = RateLimiter::<String>::new(RateLimit::new(1.0, 2));
+        limiter.capacity = 2;
+
+        assert_eq!(Ok(()), limiter.acquire("alice", 0));
+        assert_eq!(Ok(()), limiter.acquire("alice", 0));
+        assert_eq!(Ok(()), limiter.acquire("bob", 500));
+        assert_eq!(Err(1), limiter.acquire("carol", 1_000));
+        assert_eq!(2, limiter.buckets.len());
+
+        assert_eq!(Ok(()), limiter.acquire("carol", 1_500));
+        assert!(!limiter.buckets.contains_key("bob"));
+        assert_eq!(Ok(()), limiter.acquire("alice", 1_500));
+        assert_eq!(Err(1), limiter.acquire("alice", 1_500));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::http::HeaderValue;
+use tokio::task::JoinHandle;
+
+/// The header which opts a connection in to cancel-on-disconnect with a grace period in seconds
+pub const CANCEL_ON_DISCONNECT: &str = "x-cancel-on-disconnect";
+
+/// The longest grace period a session may ask for
+const MAX_GRACE: Duration = Duration::from_secs(300);
+
+/// A connection which cancels the orders of its user when it is closed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub user_id: String,
+    pub grace: Duration,
+}
+
+/// The session of a connection, if it opted in to cancel-on-disconnect
+pub type ConnectionSession = Arc<Mutex<Option<Session>>>;
+
+/// Parses the grace period of the header, which fails for invalid or too long ones
+pub fn parse_grace(value: &HeaderValue) -> Option<Duration> {
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+        .filter(|grace| *grace <= MAX_GRACE)
+}
+
+/// The opted in connections of each user
+///
+/// Orders are cancelled once the last session of a user is closed and no new
+/// one was opened within the grace period.
+#[derive(Debug, Default)]
+pub struct Sessions {
+    users: HashMap<String, UserSessions>,
+}
+
+#[derive(Debug, Default)]
+struct UserSessions {
+    connections: usize,
+    /// The cancellation which waits for the grace period to end
+    pending: Option<JoinHandle<()>>,
+}
+
+impl Sessions {
+    /// Registers a session and aborts a pending cancellation of the user
+    pub fn open(&mut self, user_id: &str) {
+        let sessions = self.users.entry(user_id.to_string()).or_default();
+        sessions.connections += 1;
+        if let Some(pending) = sessions.pending.take() {
+            pending.abort();
+        }
+    }
+
+    /// Removes a session and returns whether it was the last one of the user
+    pub fn close(&mut self, user_id: &str) -> bool {
+        let Some(sessions) = self.users.get_mut(user_id) else {
+            return false;
+        };
+        sessions.connections = sessions.connections.saturating_sub(1);
+        sessions.connections == 0
+    }
+
+    /// Remembers the cancellation of a user, which is aborted when a session is opened
+    pub fn wait(&mut self, user_id: &str, pending: JoinHandle<()>) {
+        match self.users.get_mut(user_id) {
+            Some(sessions) if sessions.connections == 0 => sessions.pending = Some(pending),
+            _ => pending.abort(),
+        }
+    }
+
+    /// Returns whether the orders of the user are due for cancellation and forgets the user
+    pub fn expire(&mut self, user_id: &str) -> bool {
+        let expired = self
+            .users
+            .get(user_id)
+            .map_or(false, |sessions| sessions.connections == 0);
+        if expired {
+            self.users.remove(user_id);
+        }
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_the_grace_period() {
+        let grace = |value| parse_grace(&HeaderValue::from_static(value));
+
+        assert_eq!(Some(Duration::from_secs(5)), grace("5"));
+        assert_eq!(Some(Duration::ZERO), grace("0"));
+        assert_eq!(None, grace("soon"));
+        assert_eq!(None, grace("301"));
+    }
+
+    #[test]
+    fn it_should_expire_after_the_last_session() {
+        let mut sessions = Sessions::default();
+        sessions.open("alice");
+        sessions.open("alice");
+
+        assert!(!sessions.close("alice"));
+        assert!(!sessions.expire("alice"));
+        assert!(sessions.close("alice"));
+        assert!(sessions.expire("alice"));
+        assert!(!sessions.expire("alice"));
+        assert!(!sessions.close("bob"));
+    }
+
+    #[tokio::test]
+    async fn it_should_abort_the_cancellation_on_reconnect() {
+        let mut sessions = Sessions::default();
+        sessions.open("alice");
+        assert!(sessions.close("alice"));
+
+        let pending = tokio::spawn(tokio::time::sleep(Duration::from_secs(60)));
+        sessions.wait("alice", pending);
+        sessions.open("alice");
+
+        let pending = sessions.users["alice"].pending.as_ref();
+        assert!(pending.is_none());
+        assert!(!sessions.expire("alice"));
+    }
+}
@@ -1,26 +1,201 @@
-use serde::Deserialize;
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer};
 use std::path::PathBuf;
 use std::thread;
 
+use crate::model::Schedule;
+
+pub use rate_limit::RateLimit;
+
+mod rate_limit;
+
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct Config {
     #[serde(default = "default_host")]
     pub host: String,
+    /// Name of the instrument which is traded
+    #[serde(default = "default_instrument")]
+    pub instrument: String,
+    /// Users which are granted the admin role when they log in
+    #[serde(default)]
+    pub admins: Vec<String>,
+    /// Keys which sign tokens in the format `kid=secret`, the first one signs new tokens
+    #[serde(default)]
+    pub signing_keys: Vec<String>,
+    /// File with one signing key per line, which are accepted after `signing_keys`
+    #[serde(default)]
+    pub signing_key_file: Option<PathBuf>,
+    /// JSON Web Key Set with public keys of an identity provider, which only verify tokens
+    #[serde(default)]
+    pub jwks_file: Option<PathBuf>,
+    /// Whether users may register themselves, otherwise admins provision them
+    #[serde(default)]
+    pub registration: bool,
+    /// Failed logins of an account until it is locked
+    #[serde(default = "default_login_max_failures")]
+    pub login_max_failures: u32,
+    /// Failed logins from an IP address until it is locked
+    #[serde(default = "default_login_max_failures_per_address")]
+    pub login_max_failures_per_address: u32,
+    /// Duration in seconds after the last failed login until a lock is lifted
+    #[serde(default = "default_login_lockout")]
+    pub login_lockout: u64,
+    /// Algorithms which incoming tokens may be signed with
+    #[serde(default = "default_token_algorithms")]
+    pub token_algorithms: Vec<String>,
+    /// Maximum difference in milliseconds between the timestamp of a signed request and now
+    #[serde(default = "default_api_key_recv_window")]
+    pub api_key_recv_window: u64,
+    /// Lifetime of issued tokens in seconds
+    #[serde(default = "default_token_lifetime")]
+    pub token_lifetime: u64,
+    /// Allowed clock skew in seconds when validating tokens
+    #[serde(default = "default_token_leeway")]
+    pub token_leeway: u64,
+    /// Issuer of tokens, which is also required when validating them
+    #[serde(default)]
+    pub token_issuer: Option<String>,
+    /// Audience of tokens, which is also required when validating them
+    #[serde(default)]
+    pub token_audience: Option<String>,
+    /// Rate limit of orders and other changes per user, e.g. `10/20` for 10 per second with bursts of 20
+    #[serde(default = "default_order_rate_limit")]
+    pub order_rate_limit: RateLimit,
+    /// Rate limit of reads per user
+    #[serde(default = "default_read_rate_limit")]
+    pub read_rate_limit: RateLimit,
+    /// Rate limit of orders and other changes per remote address
+    #[serde(default = "default_address_order_rate_limit")]
+    pub address_order_rate_limit: RateLimit,
+    /// Rate limit of reads per remote address
+    #[serde(default = "default_address_read_rate_limit")]
+    pub address_read_rate_limit: RateLimit,
     #[serde(default = "default_api_threads")]
     pub api_threads: usize,
     #[serde(default = "default_wal_location")]
     pub wal_location: PathBuf,
+    /// Trades kept in memory, older ones are read from the trade archive
+    #[serde(default = "default_trade_history")]
+    pub trade_history: usize,
+    /// Candles kept in memory per interval
+    #[serde(default = "default_candle_history")]
+    pub candle_history: usize,
+    /// Maximum slippage of market orders from the best price, e.g. `0.05` for 5%
+    #[serde(default, deserialize_with = "deserialize_slippage")]
+    pub market_slippage: Option<Decimal>,
+    /// Maximum deviation of trades from the reference price, e.g. `0.1` for 10%
+    #[serde(default)]
+    pub static_price_band: Option<Decimal>,
+    /// Maximum deviation of trades from the oldest price within the volatility window
+    #[serde(default)]
+    pub dynamic_price_band: Option<Decimal>,
+    /// Length of the volatility window in seconds
+    #[serde(default = "default_volatility_window")]
+    pub volatility_window: u64,
+    /// Duration of a volatility interruption in seconds
+    #[serde(default = "default_halt_duration")]
+    pub halt_duration: u64,
+    /// Whether a volatility interruption collects orders in an auction instead of rejecting them
+    #[serde(default)]
+    pub volatility_auction: bool,
+    /// Duration of the opening auction in seconds, or `0` to start in continuous trading
+    #[serde(default)]
+    pub opening_auction: u64,
+    /// Daily schedule of market states, e.g. `08:00=PreOpen,08:50=Auction,09:00=Continuous`
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
 }
 
 fn default_host() -> String {
     "[::]:3000".into()
 }
 
+fn default_instrument() -> String {
+    "BTC-EUR".into()
+}
+
+fn default_login_max_failures() -> u32 {
+    5
+}
+
+fn default_login_max_failures_per_address() -> u32 {
+    20
+}
+
+fn default_login_lockout() -> u64 {
+    300
+}
+
+fn default_token_algorithms() -> Vec<String> {
+    vec![
+        "HS256".into(),
+        "RS256".into(),
+        "ES256".into(),
+        "EdDSA".into(),
+    ]
+}
+
+fn default_api_key_recv_window() -> u64 {
+    5000
+}
+
+fn default_token_lifetime() -> u64 {
+    3600
+}
+
+fn default_token_leeway() -> u64 {
+    60
+}
+
+fn default_order_rate_limit() -> RateLimit {
+    RateLimit::new(10.0, 20)
+}
+
+fn default_read_rate_limit() -> RateLimit {
+    RateLimit::new(50.0, 100)
+}
+
+fn default_address_order_rate_limit() -> RateLimit {
+    RateLimit::new(50.0, 100)
+}
+
+fn default_address_read_rate_limit() -> RateLimit {
+    RateLimit::new(200.0, 400)
+}
+
 fn default_api_threads() -> usize {
     let cores: usize = thread::available_parallelism().unwrap().into();
     usize::max(1, cores - 1)
 }
 
+fn default_volatility_window() -> u64 {
+    60
+}
+
+fn default_halt_duration() -> u64 {
+    300
+}
+
 fn default_wal_location() -> PathBuf {
     "./log".into()
 }
+
+fn deserialize_slippage<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Decimal>, D::Error> {
+    let slippage = Option::<Decimal>::deserialize(deserializer)?;
+    match slippage {
+        Some(slippage) if slippage.is_sign_negative() || slippage >= Decimal::ONE => Err(
+            de::Error::custom(format!("market slippage {slippage} is not between 0 and 1")),
+        ),
+        _ => Ok(slippage),
+    }
+}
+
+fn default_trade_history() -> usize {
+    10_000
+}
+
+fn default_candle_history() -> usize {
+    1000
+}
@@ -0,0 +1,53 @@
+use serde::Deserialize;
+
+/// The limit of a token bucket
+///
+/// It is parsed from the requests per second and the burst size, e.g. `10/20`
+/// allows 20 requests at once and refills 10 of them every second.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Self { rate, burst }
+    }
+}
+
+impl TryFrom<String> for RateLimit {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let (rate, burst) = value
+            .trim()
+            .split_once('/')
+            .ok_or_else(|| format!("expected rate/burst, got {value}"))?;
+        let rate = rate.parse::<f64>().map_err(|e| e.to_string())?;
+        let burst = burst.parse::<u32>().map_err(|e| e.to_string())?;
+        if !rate.is_finite() || rate <= 0.0 || burst == 0 {
+            return Err(format!("invalid rate limit {value}"));
+        }
+
+        Ok(Self { rate, burst })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_a_rate_limit() {
+        assert_eq!(
+            Ok(RateLimit::new(0.5, 20)),
+            RateLimit::try_from("0.5/20".to_string())
+        );
+        assert!(RateLimit::try_from("10".to_string()).is_err());
+        assert!(RateLimit::try_from("0/10".to_string()).is_err());
+        assert!(RateLimit::try_from("10/0".to_string()).is_err());
+        assert!(RateLimit::try_from("inf/10".to_string()).is_err());
+    }
+}
@@ -6,14 +6,17 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 
 use crate::config::Config;
-use crate::matcher::Matcher;
-use crate::model::State;
+use crate::matcher::{Feeds, Matcher};
+use crate::model::{State, TradeArchive};
 
 mod api;
 mod config;
 mod matcher;
 mod model;
 
+/// Messages of a feed which are buffered for each subscriber
+const FEED_CAPACITY: usize = 1024;
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {}
@@ -51,22 +54,47 @@ fn main() -> Result<()> {
     // - State: Our data structure which holds the order book and trades
     // - RwLock: A lock which allows many parallel reads or one write at a time
     // - Arc: Allows different scopes to hold a reference to the lock
-    let initial_state = State::new();
+    let initial_state = State::new(&config);
     let state = Arc::new(RwLock::new(initial_state.clone()));
 
-    // Initialize the order command message channel
-    let (order_sender, order_receiver) = tokio::sync::mpsc::channel(32);
+    // Initialize the trade archive, which the API reads older trades from
+    let archive = TradeArchive::new(&config.wal_location)?;
+
+    // Initialize the matcher command message channel
+    let (command_sender, command_receiver) = tokio::sync::mpsc::channel(32);
 
     // Initialize the order book watch channel
     let (order_book_sender, order_book_receiver) =
         tokio::sync::watch::channel(initial_state.order_book);
 
+    // Initialize the execution report and order-by-order broadcast channels,
+    // slow subscribers miss messages
+    let (report_sender, _) = tokio::sync::broadcast::channel(FEED_CAPACITY);
+    let (l3_sender, _) = tokio::sync::broadcast::channel(FEED_CAPACITY);
+
     // Spawn async API threads
-    let context = api::Context::new(registry, order_book_receiver, order_sender, state.clone())?;
+    let context = api::Context::new(
+        config.clone(),
+        registry,
+        order_book_receiver,
+        report_sender.clone(),
+        l3_sender.clone(),
+        command_sender,
+        state.clone(),
+        archive.reader(),
+    )?;
     let handle = rt.spawn(api::api(config.clone(), context));
 
     // Run the matcher
-    let matcher = Matcher::new(config, rt.clone(), order_receiver, order_book_sender, state);
+    let matcher = Matcher::new(
+        config,
+        rt.clone(),
+        command_receiver,
+        order_book_sender,
+        Feeds::new(report_sender, l3_sender),
+        state,
+        archive,
+    );
     matcher.run();
 
     // Wait for API threads to finish
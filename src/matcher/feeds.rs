@@ -0,0 +1,53 @@
+use tokio::sync::broadcast::Sender;
+
+use crate::model::{ExecutionReport, L3Change, L3Event};
+
+/// The streams the matcher publishes to subscribers of the API
+#[derive(Debug)]
+pub struct Feeds {
+    reports: Sender<ExecutionReport>,
+    l3: Sender<L3Event>,
+    l3_sequence: u64,
+    /// Whether the log is replayed, during which nothing is published
+    pub replaying: bool,
+}
+
+impl Feeds {
+    pub fn new(reports: Sender<ExecutionReport>, l3: Sender<L3Event>) -> Self {
+        Self {
+            reports,
+            l3,
+            l3_sequence: 0,
+            replaying: false,
+        }
+    }
+
+    pub fn l3_sequence(&self) -> u64 {
+        self.l3_sequence
+    }
+
+    /// Sends execution reports to the subscribed users
+    pub fn publish_reports(&self, reports: Vec<ExecutionReport>) {
+        if self.replaying {
+            return;
+        }
+        for report in reports {
+            // Fails only without subscribers
+            let _ = self.reports.send(report);
+        }
+    }
+
+    /// Numbers changes of the resting orders and sends them to the subscribers
+    pub fn publish_l3(&mut self, changes: Vec<L3Change>, timestamp: u128) {
+        for change in changes {
+            self.l3_sequence += 1;
+            if !self.replaying {
+                let _ = self.l3.send(L3Event {
+                    sequence: self.l3_sequence,
+                    timestamp,
+                    change,
+                });
+            }
+        }
+    }
+}
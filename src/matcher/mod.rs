@@ -1,107 +1,797 @@
-use log::{debug, info};
+use log::{debug, info, warn};
+use rust_decimal::Decimal;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::runtime::Runtime;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::watch::Sender;
 use tokio::sync::RwLock;
+use tokio::time::timeout;
 
+pub use self::feeds::Feeds;
 use crate::config::Config;
 use crate::model::{
-    Market, MessagePort, OpenOrder, Order, OrderBook, OrderId, OrderType, State, Trade,
-    WriteAheadLog,
+    Account, AdjustBalance, AmendTradeError, Amendment, ApiKey, Balance, BatchItem, BatchResult,
+    CancelOrders, ChangeState, CircuitBreaker, Command, ExecutionReports, Internals, Interval,
+    L3Change, L3Order, L3Snapshot, Market, MarketState, MessagePort, OpenOrder, Order, OrderBook,
+    OrderId, OrderStatus, OrderType, RevokeApiKey, Schedule, Side, Snapshot, State, Trade,
+    TradeAmendment, TradeArchive, TradeId, WalEntry, WalEvent, WriteAheadLog,
 };
 
+mod feeds;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
 #[derive(Debug)]
 pub struct Matcher {
     rt: Arc<Runtime>,
-    rx: Receiver<MessagePort<OpenOrder, Order>>,
+    rx: Receiver<Command>,
     obx: Sender<OrderBook>,
+    feeds: Feeds,
     state: Arc<RwLock<State>>,
     wal: WriteAheadLog,
+    archive: TradeArchive,
+    reports: ExecutionReports,
     market: Market,
+    last_order_id: u64,
+    /// The global sequence of the latest event, see [`Order::engine_sequence`]
+    engine_sequence: u64,
+    market_state: MarketState,
+    market_state_until: Option<u128>,
+    market_slippage: Option<Decimal>,
+    circuit_breaker: CircuitBreaker,
+    halt_duration: u128,
+    volatility_auction: bool,
+    opening_auction: u128,
+    schedule: Option<Schedule>,
 }
 
 impl Matcher {
     pub fn new(
         config: Config,
         rt: Arc<Runtime>,
-        rx: Receiver<MessagePort<OpenOrder, Order>>,
+        rx: Receiver<Command>,
         obx: Sender<OrderBook>,
+        feeds: Feeds,
         state: Arc<RwLock<State>>,
+        archive: TradeArchive,
     ) -> Self {
         let wal = WriteAheadLog::new(&config.wal_location).expect("Expect wal to be initialized");
         let market = Market::new();
+        let market_slippage = config.market_slippage;
+        let circuit_breaker = CircuitBreaker::new(&config);
+        let halt_duration = config.halt_duration as u128 * NANOS_PER_SEC;
+        let opening_auction = config.opening_auction as u128 * NANOS_PER_SEC;
 
         Self {
             rt,
             rx,
             obx,
+            feeds,
             state,
             wal,
+            archive,
+            reports: ExecutionReports::default(),
             market,
+            last_order_id: 0,
+            engine_sequence: 0,
+            market_state: MarketState::Continuous,
+            market_state_until: None,
+            market_slippage,
+            circuit_breaker,
+            halt_duration,
+            volatility_auction: config.volatility_auction,
+            opening_auction,
+            schedule: config.schedule,
         }
     }
 
     pub fn run(mut self) {
-        let mut id = 0_u64;
-
-        self.restore_state();
+        let restored = self.restore_state();
+        self.start_session(restored);
 
         info!("Matcher is listening for commands");
-        while let Some(message) = self.rt.block_on(self.rx.recv()) {
-            id += 1;
+        while let Some(command) = self.receive() {
+            match command {
+                Command::Open(message) => self.handle_open(message),
+                Command::Cancel(message) => self.handle_cancel(message),
+                Command::ChangeState(message) => self.handle_change_state(message),
+                Command::AdjustBalance(message) => self.handle_adjust_balance(message),
+                Command::Snapshot(message) => {
+                    let result = self.take_snapshot();
+                    message.reply(result).unwrap();
+                }
+                Command::ReadL3(message) => {
+                    let snapshot = self.l3_snapshot();
+                    message.reply(snapshot).unwrap();
+                }
+                Command::Internals(message) => {
+                    let internals = self.internals();
+                    message.reply(internals).unwrap();
+                }
+                Command::CreateApiKey(message) => {
+                    self.save_event(&WalEvent::CreateApiKey(message.req.clone()));
+                    let key = self.create_api_key(message.req.clone());
+                    message.reply(key).unwrap();
+                }
+                Command::RevokeApiKey(message) => {
+                    let key = self.revoke_api_key(&message);
+                    if key.is_some() {
+                        self.save_event(&WalEvent::RevokeApiKey(message.req.clone()));
+                    }
+                    message.reply(key).unwrap();
+                }
+                Command::Batch(message) => self.handle_batch(message),
+                Command::AmendTrade(message) => {
+                    let amendment = self.amend_trade(message.trade_id, message.amendment, now());
+                    if let Ok(Ok(amendment)) = &amendment {
+                        self.save_event(&WalEvent::AmendTrade(amendment.clone()));
+                        self.publish_order_book();
+                    }
+                    message.reply(amendment).unwrap();
+                }
+                Command::Register(message) => {
+                    let account = self.register(message.req.clone());
+                    if account.is_some() {
+                        self.save_event(&WalEvent::Register(message.req.clone()));
+                    }
+                    message.reply(account).unwrap();
+                }
+            }
+        }
+
+        info!("Matcher stopped listening for commands");
+    }
+
+    fn handle_open(&mut self, message: MessagePort<OpenOrder, Order>) {
+        let (order, ob) = self.open(&message);
+        self.obx.send(ob).unwrap();
 
-            debug!("Processing {:?}", message.req);
+        message.reply(order).unwrap();
+    }
+
+    /// Processes all items of a batch before any other command and publishes the order book once
+    fn handle_batch(&mut self, message: MessagePort<Vec<BatchItem>, Vec<BatchResult>>) {
+        debug!("Processing batch of {} items", message.len());
+
+        let results = message
+            .iter()
+            .map(|item| match item {
+                BatchItem::Open(command) => BatchResult::Open(self.open(command).0),
+                BatchItem::Cancel(command) => {
+                    self.save_event(&WalEvent::Cancel(command.clone()));
+                    let orders = self.cancel(command);
+                    BatchResult::Cancel(orders.iter().map(|order| order.id).collect())
+                }
+            })
+            .collect();
+        self.publish_order_book();
+
+        message.reply(results).unwrap();
+    }
+
+    fn handle_cancel(&mut self, message: MessagePort<CancelOrders, Vec<OrderId>>) {
+        self.save_event(&WalEvent::Cancel(message.req.clone()));
+        let orders = self.cancel(&message);
+        self.publish_order_book();
+
+        let order_ids = orders.iter().map(|order| order.id).collect();
+        message.reply(order_ids).unwrap();
+    }
 
-            let mut order = Order::open(
-                OrderId(id),
-                message.side,
-                message.order_type,
-                message.price,
-                message.quantity,
-            );
-            self.save_command(&order);
-            let ob = self.process(&mut order);
-            self.obx.send(ob).unwrap();
+    fn handle_change_state(&mut self, message: MessagePort<ChangeState, MarketState>) {
+        let state = match message.req {
+            ChangeState::Halt => MarketState::Halted,
+            ChangeState::Resume => self.scheduled_state(now()),
+        };
+        info!("Moving into {:?} state on request", state);
+        self.change_state(state, None);
 
-            message.reply(order).unwrap();
+        message.reply(self.market_state).unwrap();
+    }
+
+    fn handle_adjust_balance(&mut self, message: MessagePort<AdjustBalance, Balance>) {
+        self.save_event(&WalEvent::AdjustBalance(message.req.clone()));
+        let balance = self.adjust_balance(&message);
+
+        message.reply(balance).unwrap();
+    }
+
+    /// Waits for the next command, changing the market state when it is due
+    fn receive(&mut self) -> Option<Command> {
+        loop {
+            let Some(deadline) = self.next_transition(now()) else {
+                return self.rt.block_on(self.rx.recv());
+            };
+
+            let wait = Duration::from_nanos(deadline.saturating_sub(now()) as u64);
+            let message = self
+                .rt
+                .block_on(async { timeout(wait, self.rx.recv()).await });
+
+            let now = now();
+            if now >= deadline {
+                let state = self.scheduled_state(now);
+                info!("Moving into {:?} state", state);
+                self.change_state(state, None);
+            }
+
+            if let Ok(message) = message {
+                return message;
+            }
         }
+    }
 
-        info!("Matcher stopped listening for commands");
+    /// Moves into the scheduled state or starts the opening auction
+    ///
+    /// An engine restored from an earlier run is already in session, so it only
+    /// follows the schedule.
+    fn start_session(&mut self, restored: bool) {
+        let now = now();
+        if self.schedule.is_some() {
+            let state = self.scheduled_state(now);
+            if state != self.market_state {
+                info!("Moving into scheduled {:?} state", state);
+                self.change_state(state, None);
+            }
+            return;
+        }
+
+        if restored || self.opening_auction == 0 || self.market_state != MarketState::Continuous {
+            return;
+        }
+
+        info!("Starting opening auction");
+        self.change_state(MarketState::Auction, Some(now + self.opening_auction));
+    }
+
+    /// Returns when the current market state ends or the schedule changes it
+    ///
+    /// A halt without an end was requested and lasts until trading is resumed.
+    fn next_transition(&self, now: u128) -> Option<u128> {
+        if self.market_state == MarketState::Halted && self.market_state_until.is_none() {
+            return None;
+        }
+
+        let scheduled = self
+            .schedule
+            .as_ref()
+            .and_then(|schedule| schedule.next_change(now));
+        match (self.market_state_until, scheduled) {
+            (Some(until), Some(scheduled)) => Some(u128::min(until, scheduled)),
+            (until, scheduled) => until.or(scheduled),
+        }
+    }
+
+    fn scheduled_state(&self, now: u128) -> MarketState {
+        self.schedule
+            .as_ref()
+            .map_or(MarketState::Continuous, |schedule| schedule.state_at(now))
+    }
+
+    /// Determines the worst price a market order may be executed at
+    ///
+    /// An explicit protection price of the client takes precedence over the
+    /// configured slippage from the best opposite price.
+    fn protection_price(&self, command: &OpenOrder) -> Option<Decimal> {
+        if command.order_type != OrderType::Market {
+            return None;
+        }
+
+        command.protection_price.or_else(|| {
+            let slippage = self.market_slippage?;
+            let best_price = self.market.best_price(!command.side)?;
+            let protection_price = match command.side {
+                Side::Buy => best_price * (Decimal::ONE + slippage),
+                Side::Sell => best_price * (Decimal::ONE - slippage),
+            };
+            Some(protection_price)
+        })
+    }
+
+    /// Restores the engine from the snapshot and the log and returns whether there was any
+    fn restore_state(&mut self) -> bool {
+        let entries = self.wal.read_entries();
+        let (has_snapshot, restored) = match self.wal.read_snapshot() {
+            Ok(Some(snapshot)) => (true, self.restore_snapshot(snapshot)),
+            Ok(None) => (false, 0),
+            Err(err) => {
+                warn!("Failed to read snapshot, replaying the whole log: {}", err);
+                (false, 0)
+            }
+        };
+        let replayed = has_snapshot || !entries.is_empty();
+
+        self.feeds.replaying = true;
+        for entry in entries.into_iter().skip(restored) {
+            match entry {
+                WalEntry::Order(mut order) => {
+                    self.last_order_id = u64::max(self.last_order_id, order.id.0);
+                    self.process(&mut order);
+                }
+                WalEntry::Event(WalEvent::Transition { state, until, at }) => {
+                    self.transition(state, until, at.unwrap_or_else(now));
+                }
+                WalEntry::Event(WalEvent::Cancel(command)) => {
+                    self.cancel(&command);
+                }
+                WalEntry::Event(WalEvent::AdjustBalance(command)) => {
+                    self.adjust_balance(&command);
+                }
+                WalEntry::Event(WalEvent::CreateApiKey(key)) => {
+                    self.create_api_key(key);
+                }
+                WalEntry::Event(WalEvent::RevokeApiKey(command)) => {
+                    self.revoke_api_key(&command);
+                }
+                WalEntry::Event(WalEvent::Register(account)) => {
+                    self.register(account);
+                }
+                WalEntry::Event(WalEvent::AmendTrade(amendment)) => {
+                    let trade_id = amendment.trade_id;
+                    match self.amend_trade(trade_id, amendment.amendment, amendment.amended_at) {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(err)) => warn!("Failed to amend trade {}: {:?}", trade_id.0, err),
+                        Err(err) => warn!("Failed to amend trade {}: {}", trade_id.0, err),
+                    }
+                }
+            }
+        }
+        self.feeds.replaying = false;
+
+        self.publish_order_book();
+        replayed
+    }
+
+    /// Restores the engine from a snapshot and returns the number of log entries it covers
+    fn restore_snapshot(&mut self, snapshot: Snapshot) -> usize {
+        info!("Restoring snapshot of {} log entries", snapshot.wal_entries);
+        let mut state = self.rt.block_on(self.state.write());
+
+        for order in snapshot.orders {
+            self.market.restore(order);
+        }
+        state
+            .order_book
+            .replace(self.market.depth(Side::Buy), self.market.depth(Side::Sell));
+        state.order_book.last = snapshot.last;
+        match snapshot.candles {
+            Some(candles) => state.candles.restore(candles),
+            None => {
+                for trade in &snapshot.trades {
+                    state.candles.push(trade);
+                }
+            }
+        }
+        match snapshot.ticker {
+            Some(ticker) => state.ticker = ticker,
+            None => {
+                for trade in &snapshot.trades {
+                    state.ticker.push(trade);
+                }
+            }
+        }
+        state.trades.restore(snapshot.trades, snapshot.trade_count);
+        state.restore_amendments(snapshot.amendments);
+        // Snapshots taken before the archive existed contain trades it misses
+        for trade in state.trades.range(1..=state.trades.count()) {
+            self.archive.append(&trade).expect("Trade not archived");
+        }
+        state.balances = snapshot.balances;
+        state.api_keys = snapshot.api_keys;
+        state.accounts = snapshot.accounts;
+        state.client_orders = snapshot.client_orders.into_iter().collect();
+        self.reports
+            .restore(snapshot.report_sequences, self.market.orders());
+
+        self.last_order_id = snapshot.last_order_id;
+        self.engine_sequence = snapshot.engine_sequence;
+        self.market_state = snapshot.market_state;
+        self.market_state_until = snapshot.market_state_until;
+        self.circuit_breaker.restore(snapshot.price_history);
+        self.update_order_book(&mut state);
+
+        snapshot.wal_entries
+    }
+
+    fn take_snapshot(&mut self) -> anyhow::Result<usize> {
+        let state = self.rt.block_on(self.state.read());
+        let snapshot = Snapshot {
+            wal_entries: self.wal.entry_count(),
+            last_order_id: self.last_order_id,
+            engine_sequence: self.engine_sequence,
+            market_state: self.market_state,
+            market_state_until: self.market_state_until,
+            last: state.order_book.last,
+            orders: self.market.orders(),
+            trades: state.trades.trades(),
+            trade_count: Some(state.trades.count()),
+            amendments: state.amendments.clone(),
+            balances: state.balances.clone(),
+            price_history: self.circuit_breaker.history().clone(),
+            api_keys: state.api_keys.clone(),
+            accounts: state.accounts.clone(),
+            client_orders: state.client_orders.orders(),
+            report_sequences: self.reports.sequences().clone(),
+            candles: Some(state.candles.candles()),
+            ticker: Some(state.ticker.clone()),
+        };
+        self.wal.write_snapshot(&snapshot)?;
+
+        info!("Stored snapshot of {} log entries", snapshot.wal_entries);
+        Ok(snapshot.wal_entries)
+    }
+
+    fn l3_snapshot(&self) -> L3Snapshot {
+        L3Snapshot {
+            sequence: self.feeds.l3_sequence(),
+            bids: self.market.queue(Side::Buy),
+            asks: self.market.queue(Side::Sell),
+        }
     }
 
-    fn restore_state(&mut self) {
-        let orders = self.wal.read_orders();
-        for mut order in orders {
-            self.process(&mut order);
+    fn internals(&self) -> Internals {
+        let state = self.rt.block_on(self.state.read());
+        Internals {
+            market_state: self.market_state,
+            market_state_until: self.market_state_until,
+            resting_orders: self.market.resting_orders(),
+            last_order_id: self.last_order_id,
+            engine_sequence: self.engine_sequence,
+            wal_entries: self.wal.entry_count(),
+            users: state.balances.len(),
         }
     }
 
+    /// Stores and processes a new order
+    ///
+    /// An order with a known client order ID of the user is not processed again,
+    /// instead the current state of the original order is returned.
+    fn open(&mut self, command: &OpenOrder) -> (Order, OrderBook) {
+        debug!("Processing {:?}", command);
+
+        if let Some(client_order_id) = &command.client_order_id {
+            let state = self.rt.block_on(self.state.read());
+            if let Some(order) = state.client_orders.get(&command.user_id, client_order_id) {
+                debug!("Returning order {} of client order ID", order.id.0);
+                return (order.clone(), state.order_book.clone());
+            }
+        }
+
+        self.last_order_id += 1;
+        let mut order = Order::open(
+            OrderId(self.last_order_id),
+            command.side,
+            command.order_type,
+            command.price,
+            command.quantity,
+        )
+        .with_protection_price(self.protection_price(command))
+        .with_time_in_force(command.time_in_force)
+        .with_user_id(command.user_id.clone())
+        .with_client_order_id(command.client_order_id.clone());
+        self.save_command(&order);
+        let mut ob = self.process(&mut order);
+        if order.status == OrderStatus::CancelledPriceBand {
+            ob = self.interrupt(order.created_at);
+        }
+        (order, ob)
+    }
+
+    /// Interrupts trading after an order would have traded outside the price band
+    fn interrupt(&mut self, at: u128) -> OrderBook {
+        let state = match self.volatility_auction {
+            true => MarketState::Auction,
+            false => MarketState::Halted,
+        };
+        info!(
+            "Moving into {:?} state as order would trade outside the price band",
+            state
+        );
+        self.save_transition(state, Some(at + self.halt_duration), at)
+    }
+
     fn save_command(&mut self, order: &Order) {
         self.wal.append_order(order).expect("Order not stored");
     }
 
+    fn save_event(&mut self, event: &WalEvent) {
+        self.wal.append_event(event).expect("Event not stored");
+    }
+
+    fn publish_order_book(&self) {
+        let state = self.rt.block_on(self.state.read());
+        self.obx.send(state.order_book.clone()).unwrap();
+    }
+
+    /// Stores and applies a market state transition and publishes the order book
+    fn change_state(&mut self, state: MarketState, until: Option<u128>) {
+        let ob = self.save_transition(state, until, now());
+        self.obx.send(ob).unwrap();
+    }
+
+    /// Stores and applies a market state transition at the given time
+    fn save_transition(&mut self, state: MarketState, until: Option<u128>, at: u128) -> OrderBook {
+        self.save_event(&WalEvent::Transition {
+            state,
+            until,
+            at: Some(at),
+        });
+        self.transition(state, until, at)
+    }
+
+    fn cancel(&mut self, command: &CancelOrders) -> Vec<Order> {
+        let mut state = self.rt.block_on(self.state.write());
+
+        let mut cancelled = self.market.cancel_where(|order| command.matches(order));
+        info!("Cancelled {} orders", cancelled.len());
+        let now = now();
+        let mut reports = Vec::with_capacity(cancelled.len());
+        let mut changes = Vec::with_capacity(cancelled.len());
+        for order in &mut cancelled {
+            self.engine_sequence += 1;
+            order.engine_sequence = self.engine_sequence;
+            state.client_orders.update(order);
+            reports.push(self.reports.close(order, now));
+            changes.push(L3Change::Delete { order_id: order.id });
+        }
+        self.feeds.publish_reports(reports);
+        self.feeds.publish_l3(changes, now);
+
+        state
+            .order_book
+            .replace(self.market.depth(Side::Buy), self.market.depth(Side::Sell));
+        self.update_order_book(&mut state);
+        cancelled
+    }
+
+    /// Busts or corrects a trade, unless it is unknown or was busted before
+    fn amend_trade(
+        &mut self,
+        trade_id: TradeId,
+        amendment: Amendment,
+        amended_at: u128,
+    ) -> anyhow::Result<Result<TradeAmendment, AmendTradeError>> {
+        // Only the archive keeps the owners of trades restored from a snapshot
+        let Some(trade) = self.archive.get(trade_id.0 as usize)? else {
+            return Ok(Err(AmendTradeError::UnknownTrade));
+        };
+        // The candles are rebuilt from the trades of the day, up to the latest one replayed
+        let count = self.rt.block_on(self.state.read()).trades.count();
+        let archive = self.archive.reader();
+        let start = Interval::Day.start(trade.executed_at);
+        let first = archive.executed_before(start) + 1;
+        let last = archive.executed_before(start + Interval::Day.nanos());
+        let day = archive.read(first..=last.min(count))?;
+
+        let mut state = self.rt.block_on(self.state.write());
+        let trade = match state.amendment(trade_id) {
+            Some(previous) => match previous.amendment.apply(trade) {
+                Some(trade) => trade,
+                None => return Ok(Err(AmendTradeError::Busted)),
+            },
+            None => trade,
+        };
+
+        info!("Amending trade {} with {:?}", trade_id.0, amendment);
+        self.engine_sequence += 1;
+        let amendment = TradeAmendment {
+            trade_id,
+            engine_sequence: self.engine_sequence,
+            amendment,
+            amended_at,
+        };
+        state.amend_trade(
+            &Trade {
+                id: trade_id,
+                ..trade
+            },
+            amendment.clone(),
+            day,
+        );
+        Ok(Ok(amendment))
+    }
+
+    fn create_api_key(&mut self, key: ApiKey) -> ApiKey {
+        let mut state = self.rt.block_on(self.state.write());
+        state.api_keys.insert(key.key.clone(), key.clone());
+        key
+    }
+
+    /// Removes an API key if it belongs to the user
+    fn revoke_api_key(&mut self, command: &RevokeApiKey) -> Option<ApiKey> {
+        let mut state = self.rt.block_on(self.state.write());
+        let owned = state
+            .api_keys
+            .get(&command.key)
+            .map_or(false, |key| key.user_id == command.user_id);
+        if !owned {
+            return None;
+        }
+        state.api_keys.remove(&command.key)
+    }
+
+    /// Stores an account if its user ID is not taken yet
+    fn register(&mut self, account: Account) -> Option<Account> {
+        let mut state = self.rt.block_on(self.state.write());
+        if state.accounts.contains_key(&account.user_id) {
+            return None;
+        }
+        state
+            .accounts
+            .insert(account.user_id.clone(), account.clone());
+        Some(account)
+    }
+
+    fn adjust_balance(&mut self, command: &AdjustBalance) -> Balance {
+        let mut state = self.rt.block_on(self.state.write());
+        let balance = state.balance_mut(&command.user_id);
+        balance.adjust(command.asset, command.amount);
+        *balance
+    }
+
+    /// Moves into a market state at the given time, which uncrossed trades are stamped with
+    fn transition(
+        &mut self,
+        market_state: MarketState,
+        until: Option<u128>,
+        at: u128,
+    ) -> OrderBook {
+        let mut state = self.rt.block_on(self.state.write());
+
+        if market_state.uncrosses() {
+            if let Some(equilibrium) = self.market.equilibrium(state.order_book.last) {
+                info!(
+                    "Uncrossing auction with {} at {}",
+                    equilibrium.volume, equilibrium.price
+                );
+                for trade in self.market.uncross(equilibrium.price) {
+                    self.engine_sequence += 1;
+                    let trade = trade
+                        .with_id(state.next_trade_id())
+                        .with_engine_sequence(self.engine_sequence)
+                        .with_executed_at(at);
+                    self.circuit_breaker.record(trade.executed_at, trade.price);
+                    let reports = self.reports.fill(&trade);
+                    let changes = reports.iter().map(L3Change::of_fill).collect();
+                    self.feeds.publish_l3(changes, trade.executed_at);
+                    self.feeds.publish_reports(reports);
+                    let trade = state.push_trade(trade);
+                    self.archive.append(&trade).expect("Trade not archived");
+                }
+                state
+                    .order_book
+                    .replace(self.market.depth(Side::Buy), self.market.depth(Side::Sell));
+            }
+        }
+
+        if market_state == MarketState::Closed {
+            let mut expired = self.market.expire_day_orders();
+            info!("Expiring {} day orders", expired.len());
+            let mut reports = Vec::with_capacity(expired.len());
+            let mut changes = Vec::with_capacity(expired.len());
+            for order in &mut expired {
+                self.engine_sequence += 1;
+                order.engine_sequence = self.engine_sequence;
+                state.client_orders.update(order);
+                reports.push(self.reports.close(order, at));
+                changes.push(L3Change::Delete { order_id: order.id });
+            }
+            self.feeds.publish_reports(reports);
+            self.feeds.publish_l3(changes, at);
+            state
+                .order_book
+                .replace(self.market.depth(Side::Buy), self.market.depth(Side::Sell));
+        }
+
+        if market_state == MarketState::Continuous {
+            self.circuit_breaker.reset();
+        }
+
+        self.market_state = market_state;
+        self.market_state_until = until;
+        self.update_order_book(&mut state)
+    }
+
     fn process(&mut self, order: &mut Order) -> OrderBook {
         let mut state = self.rt.block_on(self.state.write());
+        self.engine_sequence += 1;
+        order.engine_sequence = self.engine_sequence;
 
-        let trades = self.market.push(order);
+        let trades = match self.market_state {
+            MarketState::Continuous => {
+                let band = self.circuit_breaker.band(order.created_at);
+                self.market.push(order, band)
+            }
+            MarketState::PreOpen | MarketState::Auction => {
+                self.market.push_auction(order);
+                Vec::new()
+            }
+            MarketState::Halted | MarketState::Closed => {
+                debug!(
+                    "Rejecting order while the market is {:?}",
+                    self.market_state
+                );
+                order.reject();
+                Vec::new()
+            }
+        };
 
+        let mut reports = vec![self.reports.accept(order, order.created_at)];
+        let mut changes = Vec::new();
         for trade in trades {
+            self.engine_sequence += 1;
+            let trade = trade
+                .with_id(state.next_trade_id())
+                .with_engine_sequence(self.engine_sequence);
+            order.engine_sequence = self.engine_sequence;
+            self.circuit_breaker.record(order.created_at, trade.price);
+            for report in self.reports.fill(&trade) {
+                if report.order_id != order.id {
+                    changes.push(L3Change::of_fill(&report));
+                }
+                reports.push(report);
+            }
             let Trade {
                 price, quantity, ..
             } = trade;
             state.order_book.take(!order.side, price, quantity);
-            state.push_trade(trade);
+            let trade = state.push_trade(trade);
+            self.archive.append(&trade).expect("Trade not archived");
             debug!("Taking liquidity of {} at {}", quantity, price);
         }
 
-        if order.order_type == OrderType::Limit && !order.is_filled() {
-            debug!("Placing order of {} at {}", order.unfilled(), order.price);
-            state
-                .order_book
-                .place(order.side, order.price, order.unfilled());
+        match order.status {
+            OrderStatus::Open | OrderStatus::PartiallyFilled => {
+                debug!("Placing order of {} at {}", order.unfilled(), order.price);
+                state
+                    .order_book
+                    .place(order.side, order.price, order.unfilled());
+                let position = self.market.level_len(order.side, order.price) - 1;
+                changes.push(L3Change::Add(L3Order::new(order, position)));
+            }
+            OrderStatus::CancelledNoLiquidity => {
+                debug!("Cancelling unfilled market order of {}", order.unfilled());
+            }
+            OrderStatus::CancelledPriceBand => {
+                debug!("Cancelling order outside the price band");
+            }
+            OrderStatus::Filled
+            | OrderStatus::Cancelled
+            | OrderStatus::Rejected
+            | OrderStatus::Expired => {}
         }
+        let closed = matches!(
+            order.status,
+            OrderStatus::CancelledNoLiquidity | OrderStatus::CancelledPriceBand
+        );
+        if closed {
+            self.engine_sequence += 1;
+            order.engine_sequence = self.engine_sequence;
+            reports.push(self.reports.close(order, order.created_at));
+        }
+        state.client_orders.update(order);
+        self.feeds.publish_reports(reports);
+        self.feeds.publish_l3(changes, order.created_at);
 
+        self.update_order_book(&mut state)
+    }
+
+    /// Updates the market state and auction price of the order book
+    fn update_order_book(&self, state: &mut State) -> OrderBook {
+        state.order_book.state = self.market_state;
+        state.order_book.indicative = match self.market_state {
+            MarketState::Auction => self.market.equilibrium(state.order_book.last),
+            MarketState::PreOpen
+            | MarketState::Continuous
+            | MarketState::Halted
+            | MarketState::Closed => None,
+        };
         state.order_book.clone()
     }
 }
+
+fn now() -> u128 {
+    let now = SystemTime::now();
+    now.duration_since(UNIX_EPOCH).unwrap().as_nanos()
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+/// The credentials of a registered user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Account {
+    pub user_id: String,
+    /// The Argon2 hash of the password in the PHC string format
+    pub password_hash: String,
+    /// Roles which are granted on login in addition to those of the config
+    #[serde(default)]
+    pub roles: Vec<String>,
+    pub created_at: u128,
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+/// A permission of an API key
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Scope {
+    /// Reading market data and the own account
+    Read,
+    /// Opening and cancelling orders
+    Trade,
+    /// Withdrawing balances
+    Withdraw,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::Read, Scope::Trade, Scope::Withdraw];
+}
+
+/// A key pair which signs the requests of a user for programmatic access
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKey {
+    /// The public identifier of the key
+    pub key: String,
+    /// The secret of the HMAC signature, which is only shown on creation
+    pub secret: String,
+    pub user_id: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: u128,
+}
+
+/// Revokes an API key of a user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevokeApiKey {
+    pub user_id: String,
+    pub key: String,
+}
@@ -0,0 +1,171 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::model::PricePair;
+
+/// The single price at which a call auction would be uncrossed
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Equilibrium {
+    pub price: Decimal,
+    pub volume: Decimal,
+    /// Surplus of buy over sell quantity at the price, negative for a sell surplus
+    pub imbalance: Decimal,
+}
+
+impl Equilibrium {
+    /// Finds the price which maximises the executable volume
+    ///
+    /// Ties are broken by the lowest absolute imbalance, then by market
+    /// pressure: the highest price if all candidates have a buy surplus, the
+    /// lowest if all have a sell surplus. Remaining ties go to the price
+    /// closest to the reference price, then to the lowest price.
+    pub fn find(
+        bids: &[PricePair],
+        asks: &[PricePair],
+        reference: Option<Decimal>,
+    ) -> Option<Self> {
+        let best_bid = bids.first()?.price;
+        let best_ask = asks.first()?.price;
+
+        let mut prices = bids
+            .iter()
+            .chain(asks)
+            .map(|level| level.price)
+            .filter(|&price| best_ask <= price && price <= best_bid)
+            .collect::<Vec<_>>();
+        prices.sort();
+        prices.dedup();
+
+        let candidates = prices
+            .into_iter()
+            .map(|price| Self::at(bids, asks, price))
+            .collect::<Vec<_>>();
+
+        let volume = candidates.iter().map(|c| c.volume).max()?;
+        let candidates =
+            Self::keep_min_by_key(candidates.into_iter().filter(|c| c.volume == volume), |c| {
+                c.imbalance.abs()
+            });
+
+        if candidates.iter().all(|c| c.imbalance > Decimal::ZERO) {
+            return candidates.last().copied();
+        }
+        if candidates.iter().all(|c| c.imbalance < Decimal::ZERO) {
+            return candidates.first().copied();
+        }
+
+        match reference {
+            Some(reference) => {
+                Self::keep_min_by_key(candidates.into_iter(), |c| (c.price - reference).abs())
+                    .first()
+                    .copied()
+            }
+            None => candidates.first().copied(),
+        }
+    }
+
+    fn at(bids: &[PricePair], asks: &[PricePair], price: Decimal) -> Self {
+        let buy_volume: Decimal = bids
+            .iter()
+            .take_while(|level| level.price >= price)
+            .map(|level| level.quantity)
+            .sum();
+        let sell_volume: Decimal = asks
+            .iter()
+            .take_while(|level| level.price <= price)
+            .map(|level| level.quantity)
+            .sum();
+
+        Self {
+            price,
+            volume: Decimal::min(buy_volume, sell_volume),
+            imbalance: buy_volume - sell_volume,
+        }
+    }
+
+    fn keep_min_by_key<I, F>(candidates: I, key: F) -> Vec<Self>
+    where
+        I: Iterator<Item = Self>,
+        F: Fn(&Self) -> Decimal,
+    {
+        let candidates = candidates.collect::<Vec<_>>();
+        let Some(min) = candidates.iter().map(&key).min() else {
+            return candidates;
+        };
+        candidates.into_iter().filter(|c| key(c) == min).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn levels(levels: &[(Decimal, Decimal)]) -> Vec<PricePair> {
+        levels
+            .iter()
+            .map(|&(price, quantity)| PricePair::new(price, quantity))
+            .collect()
+    }
+
+    #[test]
+    fn should_not_find_an_uncrossed_book() {
+        let bids = levels(&[(dec!(9), dec!(100))]);
+        let asks = levels(&[(dec!(10), dec!(100))]);
+
+        assert_eq!(Equilibrium::find(&bids, &asks, None), None);
+    }
+
+    #[test]
+    fn should_maximise_the_executable_volume() {
+        let bids = levels(&[(dec!(12), dec!(100)), (dec!(11), dec!(200))]);
+        let asks = levels(&[(dec!(10), dec!(150)), (dec!(11), dec!(100))]);
+
+        assert_eq!(
+            Equilibrium::find(&bids, &asks, None),
+            Some(Equilibrium {
+                price: dec!(11),
+                volume: dec!(250),
+                imbalance: dec!(50),
+            })
+        );
+    }
+
+    #[test]
+    fn should_prefer_the_lowest_imbalance() {
+        let bids = levels(&[(dec!(12), dec!(100))]);
+        let asks = levels(&[
+            (dec!(10), dec!(80)),
+            (dec!(11), dec!(20)),
+            (dec!(12), dec!(10)),
+        ]);
+
+        let equilibrium = Equilibrium::find(&bids, &asks, None).unwrap();
+        assert_eq!(equilibrium.price, dec!(11));
+        assert_eq!(equilibrium.volume, dec!(100));
+        assert_eq!(equilibrium.imbalance, dec!(0));
+    }
+
+    #[test]
+    fn should_follow_the_market_pressure() {
+        let bids = levels(&[(dec!(12), dec!(300))]);
+        let asks = levels(&[(dec!(10), dec!(100)), (dec!(11), dec!(100))]);
+
+        let equilibrium = Equilibrium::find(&bids, &asks, None).unwrap();
+        assert_eq!(equilibrium.price, dec!(12));
+        assert_eq!(equilibrium.volume, dec!(200));
+        assert_eq!(equilibrium.imbalance, dec!(100));
+    }
+
+    #[test]
+    fn should_use_the_reference_price() {
+        let bids = levels(&[(dec!(12), dec!(100))]);
+        let asks = levels(&[(dec!(10), dec!(100))]);
+
+        let equilibrium = Equilibrium::find(&bids, &asks, Some(dec!(11.8))).unwrap();
+        assert_eq!(equilibrium.price, dec!(12));
+
+        let equilibrium = Equilibrium::find(&bids, &asks, None).unwrap();
+        assert_eq!(equilibrium.price, dec!(10));
+    }
+}
@@ -0,0 +1,87 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::model::Trade;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Asset {
+    /// The asset which is traded
+    Base,
+    /// The asset prices are quoted in
+    Quote,
+}
+
+/// The holdings of a user, which may become negative as trades are settled
+/// without checking funds first
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Balance {
+    pub base: Decimal,
+    pub quote: Decimal,
+}
+
+impl Balance {
+    pub fn adjust(&mut self, asset: Asset, amount: Decimal) {
+        match asset {
+            Asset::Base => self.base += amount,
+            Asset::Quote => self.quote += amount,
+        }
+    }
+
+    /// Settles the buying side of a trade
+    pub fn buy(&mut self, trade: &Trade) {
+        self.base += trade.quantity;
+        self.quote -= trade.quantity * trade.price;
+    }
+
+    /// Settles the selling side of a trade
+    pub fn sell(&mut self, trade: &Trade) {
+        self.base -= trade.quantity;
+        self.quote += trade.quantity * trade.price;
+    }
+
+    /// Reverses the settlement of the buying side of a busted or corrected trade
+    pub fn reverse_buy(&mut self, trade: &Trade) {
+        self.base -= trade.quantity;
+        self.quote += trade.quantity * trade.price;
+    }
+
+    /// Reverses the settlement of the selling side of a busted or corrected trade
+    pub fn reverse_sell(&mut self, trade: &Trade) {
+        self.base += trade.quantity;
+        self.quote -= trade.quantity * trade.price;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Order, OrderId, Side};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn should_adjust_an_asset() {
+        let mut balance = Balance::default();
+        balance.adjust(Asset::Base, dec!(10));
+        balance.adjust(Asset::Quote, dec!(-5));
+
+        assert_eq!(balance.base, dec!(10));
+        assert_eq!(balance.quote, dec!(-5));
+    }
+
+    #[test]
+    fn should_reverse_a_settlement() {
+        let bid = Order::open_limit(OrderId(1), Side::Buy, dec!(10), dec!(2));
+        let ask = Order::open_limit(OrderId(2), Side::Sell, dec!(10), dec!(2));
+        let trade = Trade::new(dec!(10), dec!(2), &bid, &ask);
+        let mut buyer = Balance::default();
+        let mut seller = Balance::default();
+        buyer.buy(&trade);
+        seller.sell(&trade);
+        assert_eq!((buyer.base, buyer.quote), (dec!(2), dec!(-20)));
+
+        buyer.reverse_buy(&trade);
+        seller.reverse_sell(&trade);
+        assert_eq!(buyer, Balance::default());
+        assert_eq!(seller, Balance::default());
+    }
+}
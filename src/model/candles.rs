@@ -0,0 +1,297 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+use crate::model::Trade;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// The duration of a candle
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Interval {
+    #[serde(rename = "1s")]
+    Second,
+    #[serde(rename = "1m")]
+    Minute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    Hour,
+    #[serde(rename = "1d")]
+    Day,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 5] = [
+        Interval::Second,
+        Interval::Minute,
+        Interval::FiveMinutes,
+        Interval::Hour,
+        Interval::Day,
+    ];
+
+    pub fn nanos(&self) -> u128 {
+        let secs = match self {
+            Interval::Second => 1,
+            Interval::Minute => 60,
+            Interval::FiveMinutes => 5 * 60,
+            Interval::Hour => 60 * 60,
+            Interval::Day => 24 * 60 * 60,
+        };
+        secs * NANOS_PER_SEC
+    }
+
+    /// Returns the start of the candle which contains the time
+    pub fn start(&self, time: u128) -> u128 {
+        time - time % self.nanos()
+    }
+}
+
+/// The open, high, low and close price and the volume of the trades in an interval
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Candle {
+    pub interval: Interval,
+    /// Nanoseconds since the epoch when the interval starts
+    pub open_time: u128,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+    pub quote_volume: Decimal,
+    pub trades: u64,
+}
+
+impl Candle {
+    fn new(interval: Interval, trade: &Trade) -> Self {
+        Self {
+            interval,
+            open_time: interval.start(trade.executed_at),
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: trade.quantity,
+            quote_volume: trade.price * trade.quantity,
+            trades: 1,
+        }
+    }
+
+    fn push(&mut self, trade: &Trade) {
+        self.high = Decimal::max(self.high, trade.price);
+        self.low = Decimal::min(self.low, trade.price);
+        self.close = trade.price;
+        self.volume += trade.quantity;
+        self.quote_volume += trade.price * trade.quantity;
+        self.trades += 1;
+    }
+}
+
+/// The latest candles of each interval
+///
+/// Intervals without trades have no candle, so the history reaches further
+/// back in quiet markets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Candles {
+    /// The number of candles kept per interval
+    history: usize,
+    series: HashMap<Interval, VecDeque<Candle>>,
+}
+
+impl Candles {
+    pub fn new(history: usize) -> Self {
+        Self {
+            history,
+            series: HashMap::new(),
+        }
+    }
+
+    /// Adds a trade to the current candle of each interval, or opens a new one
+    pub fn push(&mut self, trade: &Trade) {
+        for interval in Interval::ALL {
+            let series = self.series.entry(interval).or_default();
+            let open_time = interval.start(trade.executed_at);
+            match series.back_mut() {
+                Some(candle) if candle.open_time >= open_time => candle.push(trade),
+                _ => {
+                    series.push_back(Candle::new(interval, trade));
+                    if series.len() > self.history {
+                        series.pop_front();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rebuilds the candles which contain an amended trade
+    ///
+    /// The given trades already reflect the amendment and include all trades of
+    /// the day the amended trade was executed, so of every candle containing it.
+    pub fn amend(&mut self, trade: &Trade, trades: &[Trade]) {
+        for interval in Interval::ALL {
+            let Some(series) = self.series.get_mut(&interval) else {
+                continue;
+            };
+            let open_time = interval.start(trade.executed_at);
+            let Some(position) = series
+                .iter()
+                .position(|candle| candle.open_time == open_time)
+            else {
+                continue;
+            };
+
+            let candle = trades
+                .iter()
+                .filter(|trade| interval.start(trade.executed_at) == open_time)
+                .fold(None, |candle: Option<Candle>, trade| match candle {
+                    Some(mut candle) => {
+                        candle.push(trade);
+                        Some(candle)
+                    }
+                    None => Some(Candle::new(interval, trade)),
+                });
+            match candle {
+                Some(candle) => series[position] = candle,
+                None => {
+                    series.remove(position);
+                }
+            }
+        }
+    }
+
+    /// Returns the candles of an interval which open within the time range, oldest first
+    pub fn range(&self, interval: Interval, from: Option<u128>, to: Option<u128>) -> Vec<Candle> {
+        let Some(series) = self.series.get(&interval) else {
+            return Vec::new();
+        };
+        series
+            .iter()
+            .filter(|candle| from.map_or(true, |from| candle.open_time >= interval.start(from)))
+            .filter(|candle| to.map_or(true, |to| candle.open_time <= to))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the current candle of an interval
+    pub fn latest(&self, interval: Interval) -> Option<&Candle> {
+        self.series.get(&interval)?.back()
+    }
+
+    /// Returns the candles of all intervals
+    pub fn candles(&self) -> Vec<Candle> {
+        Interval::ALL
+            .iter()
+            .filter_map(|interval| self.series.get(interval))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Replaces the candles, keeping the latest ones of each interval
+    pub fn restore(&mut self, candles: Vec<Candle>) {
+        self.series.clear();
+        for candle in candles {
+            let series = self.series.entry(candle.interval).or_default();
+            series.push_back(candle);
+            if series.len() > self.history {
+                series.pop_front();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Order, OrderId, Side};
+    use rust_decimal_macros::dec;
+
+    fn trade(price: Decimal, quantity: Decimal, secs: u128) -> Trade {
+        let bid = Order::open_limit(OrderId(1), Side::Buy, price, quantity);
+        let ask = Order::open_limit(OrderId(2), Side::Sell, price, quantity);
+        let mut trade = Trade::new(price, quantity, &bid, &ask);
+        trade.executed_at = secs * NANOS_PER_SEC;
+        trade
+    }
+
+    #[test]
+    fn should_aggregate_trades_into_candles() {
+        let mut candles = Candles::new(10);
+        candles.push(&trade(dec!(10), dec!(1), 60));
+        candles.push(&trade(dec!(12), dec!(2), 61));
+        candles.push(&trade(dec!(9), dec!(1), 119));
+        assert_eq!(candles.latest(Interval::Second).unwrap().close, dec!(9));
+
+        let minutes = candles.range(Interval::Minute, None, None);
+        assert_eq!(
+            minutes,
+            vec![Candle {
+                interval: Interval::Minute,
+                open_time: 60 * NANOS_PER_SEC,
+                open: dec!(10),
+                high: dec!(12),
+                low: dec!(9),
+                close: dec!(9),
+                volume: dec!(4),
+                quote_volume: dec!(43),
+                trades: 3,
+            }]
+        );
+
+        let seconds = candles.range(Interval::Second, Some(61 * NANOS_PER_SEC), None);
+        assert_eq!(seconds.len(), 2);
+        assert_eq!(seconds[0].close, dec!(12));
+    }
+
+    #[test]
+    fn should_amend_candles() {
+        let mut candles = Candles::new(10);
+        let first = trade(dec!(10), dec!(1), 60);
+        let busted = trade(dec!(20), dec!(2), 61);
+        let last = trade(dec!(12), dec!(1), 62);
+        for trade in [&first, &busted, &last] {
+            candles.push(trade);
+        }
+
+        candles.amend(&busted, &[first.clone(), last.clone()]);
+        let minute = candles.latest(Interval::Minute).unwrap();
+        assert_eq!(
+            (minute.open, minute.high, minute.volume, minute.trades),
+            (dec!(10), dec!(12), dec!(2), 2)
+        );
+        assert_eq!(candles.range(Interval::Second, None, None).len(), 2);
+
+        let corrected = Trade {
+            price: dec!(11),
+            ..last.clone()
+        };
+        candles.amend(&last, &[first, corrected]);
+        let minute = candles.latest(Interval::Minute).unwrap();
+        assert_eq!(
+            (
+                minute.high,
+                minute.close,
+                minute.volume,
+                minute.quote_volume,
+                minute.trades
+            ),
+            (dec!(11), dec!(11), dec!(2), dec!(21), 2)
+        );
+    }
+
+    #[test]
+    fn should_keep_a_bounded_history() {
+        let mut candles = Candles::new(2);
+        for secs in 0..5 {
+            candles.push(&trade(dec!(10), dec!(1), secs));
+        }
+
+        let seconds = candles.range(Interval::Second, None, None);
+        assert_eq!(seconds.len(), 2);
+        assert_eq!(seconds[0].open_time, 3 * NANOS_PER_SEC);
+
+        let mut restored = Candles::new(2);
+        restored.restore(candles.candles());
+        assert_eq!(restored, candles);
+    }
+}
@@ -0,0 +1,167 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::config::Config;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// The range of prices trades may currently be executed at
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PriceBand {
+    pub low: Decimal,
+    pub high: Decimal,
+}
+
+impl PriceBand {
+    pub fn around(reference: Decimal, deviation: Decimal) -> Self {
+        Self {
+            low: reference * (Decimal::ONE - deviation),
+            high: reference * (Decimal::ONE + deviation),
+        }
+    }
+
+    pub fn contains(&self, price: Decimal) -> bool {
+        self.low <= price && price <= self.high
+    }
+
+    fn intersect(self, other: Self) -> Self {
+        Self {
+            low: Decimal::max(self.low, other.low),
+            high: Decimal::min(self.high, other.high),
+        }
+    }
+}
+
+/// Limits the prices trades may be executed at based on recent prices
+///
+/// The static band is measured from a reference price, which is the first
+/// trade price and is reset whenever continuous trading resumes. The
+/// dynamic band is measured from the oldest trade within the volatility window.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    static_band: Option<Decimal>,
+    dynamic_band: Option<Decimal>,
+    window: u128,
+    history: PriceHistory,
+}
+
+/// The prices the bands are measured from, which are part of a snapshot
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceHistory {
+    reference_price: Option<Decimal>,
+    last_price: Option<Decimal>,
+    recent_trades: VecDeque<(u128, Decimal)>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            static_band: config.static_price_band,
+            dynamic_band: config.dynamic_price_band,
+            window: config.volatility_window as u128 * NANOS_PER_SEC,
+            history: PriceHistory::default(),
+        }
+    }
+
+    pub fn history(&self) -> &PriceHistory {
+        &self.history
+    }
+
+    pub fn restore(&mut self, history: PriceHistory) {
+        self.history = history;
+    }
+
+    /// Measures both bands from the last price again
+    pub fn reset(&mut self) {
+        self.history.reference_price = self.history.last_price;
+        self.history.recent_trades.clear();
+    }
+
+    /// Returns the band trades may be executed in at the given time
+    pub fn band(&mut self, now: u128) -> Option<PriceBand> {
+        let recent_trades = &mut self.history.recent_trades;
+        while let Some(&(executed_at, _)) = recent_trades.front() {
+            if executed_at + self.window >= now {
+                break;
+            }
+            recent_trades.pop_front();
+        }
+
+        let static_band = self
+            .static_band
+            .zip(self.history.reference_price)
+            .map(|(deviation, reference)| PriceBand::around(reference, deviation));
+        let dynamic_band = self
+            .dynamic_band
+            .zip(self.history.recent_trades.front())
+            .map(|(deviation, &(_, reference))| PriceBand::around(reference, deviation));
+
+        match (static_band, dynamic_band) {
+            (Some(static_band), Some(dynamic_band)) => Some(static_band.intersect(dynamic_band)),
+            (static_band, dynamic_band) => static_band.or(dynamic_band),
+        }
+    }
+
+    /// Records a trade price at the time of the order which caused it
+    pub fn record(&mut self, at: u128, price: Decimal) {
+        self.history.reference_price.get_or_insert(price);
+        self.history.last_price = Some(price);
+        self.history.recent_trades.push_back((at, price));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn config() -> Config {
+        Config {
+            static_price_band: Some(dec!(0.2)),
+            dynamic_price_band: Some(dec!(0.1)),
+            volatility_window: 60,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn should_not_limit_before_the_first_trade() {
+        let mut breaker = CircuitBreaker::new(&config());
+        assert_eq!(breaker.band(0), None);
+    }
+
+    #[test]
+    fn should_intersect_static_and_dynamic_band() {
+        let mut breaker = CircuitBreaker::new(&config());
+        breaker.record(0, dec!(100));
+        breaker.record(NANOS_PER_SEC, dec!(108));
+
+        let band = breaker.band(2 * NANOS_PER_SEC).unwrap();
+        assert_eq!(band.low, dec!(90));
+        assert_eq!(band.high, dec!(110));
+    }
+
+    #[test]
+    fn should_move_the_dynamic_band_with_the_window() {
+        let mut breaker = CircuitBreaker::new(&config());
+        breaker.record(0, dec!(100));
+        breaker.record(30 * NANOS_PER_SEC, dec!(115));
+
+        let band = breaker.band(90 * NANOS_PER_SEC).unwrap();
+        assert_eq!(band.low, dec!(103.5));
+        assert_eq!(band.high, dec!(120));
+    }
+
+    #[test]
+    fn should_measure_from_the_last_price_after_reset() {
+        let mut breaker = CircuitBreaker::new(&config());
+        breaker.record(0, dec!(100));
+        breaker.record(0, dec!(105));
+        breaker.reset();
+
+        let band = breaker.band(300 * NANOS_PER_SEC).unwrap();
+        assert_eq!(band.low, dec!(84));
+        assert_eq!(band.high, dec!(126));
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use crate::model::{Order, OrderId, Trade};
+
+/// Orders with a client order ID, which is unique per user
+///
+/// The orders are kept up to date with their fills, cancellations and expiry,
+/// so a resubmission or a query returns their current state.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientOrders {
+    /// Orders by their owner and client order ID
+    orders: HashMap<String, HashMap<String, Order>>,
+    /// Owners and client order IDs by order ID
+    keys: HashMap<OrderId, (String, String)>,
+}
+
+impl ClientOrders {
+    pub fn get(&self, user_id: &str, client_order_id: &str) -> Option<&Order> {
+        self.orders.get(user_id)?.get(client_order_id)
+    }
+
+    /// Adds an order or replaces its state, unless it has no client order ID
+    pub fn update(&mut self, order: &Order) {
+        let Some(client_order_id) = &order.client_order_id else {
+            return;
+        };
+        self.keys
+            .insert(order.id, (order.user_id.clone(), client_order_id.clone()));
+        self.orders
+            .entry(order.user_id.clone())
+            .or_default()
+            .insert(client_order_id.clone(), order.clone());
+    }
+
+    /// Fills both orders of a trade, if they are known
+    pub fn fill(&mut self, trade: &Trade) {
+        for order_id in [trade.buy_order_id, trade.sell_order_id] {
+            if let Some(order) = self.get_mut(order_id) {
+                order.fill(trade.quantity);
+                order.engine_sequence = trade.engine_sequence;
+            }
+        }
+    }
+
+    /// Returns all orders in the order they were opened
+    pub fn orders(&self) -> Vec<Order> {
+        let mut orders = self
+            .orders
+            .values()
+            .flat_map(|orders| orders.values().cloned())
+            .collect::<Vec<_>>();
+        orders.sort_by_key(|order| order.id.0);
+        orders
+    }
+
+    fn get_mut(&mut self, order_id: OrderId) -> Option<&mut Order> {
+        let (user_id, client_order_id) = self.keys.get(&order_id)?;
+        self.orders.get_mut(user_id)?.get_mut(client_order_id)
+    }
+}
+
+impl FromIterator<Order> for ClientOrders {
+    fn from_iter<T: IntoIterator<Item = Order>>(iter: T) -> Self {
+        let mut client_orders = Self::default();
+        for order in iter {
+            client_orders.update(&order);
+        }
+        client_orders
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{OrderStatus, Side};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn should_track_the_state_of_client_orders() {
+        let bid = Order::open_limit(OrderId(1), Side::Buy, dec!(10), dec!(100))
+            .with_user_id("alice".into())
+            .with_client_order_id(Some("a-1".into()));
+        let mut ask = Order::open_limit(OrderId(2), Side::Sell, dec!(10), dec!(40))
+            .with_user_id("bob".into());
+
+        let mut client_orders = ClientOrders::default();
+        client_orders.update(&bid);
+        client_orders.update(&ask);
+        assert_eq!(client_orders.get("bob", ""), None);
+        assert_eq!(client_orders.get("alice", "a-1"), Some(&bid));
+        assert_eq!(client_orders.get("bob", "a-1"), None);
+
+        let mut maker = bid.clone();
+        ask.fill(dec!(40));
+        let trade = Trade::new(dec!(10), maker.fill(dec!(40)), &maker, &ask);
+        client_orders.fill(&trade);
+        let order = client_orders.get("alice", "a-1").unwrap();
+        assert_eq!(order.status, OrderStatus::PartiallyFilled);
+        assert_eq!(order.filled, dec!(40));
+
+        maker.cancel();
+        client_orders.update(&maker);
+        assert_eq!(
+            client_orders.get("alice", "a-1").unwrap().status,
+            OrderStatus::Cancelled
+        );
+
+        let restored = client_orders.orders().into_iter().collect::<ClientOrders>();
+        assert_eq!(client_orders, restored);
+    }
+}
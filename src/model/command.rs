@@ -0,0 +1,239 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::model::{
+    Account, AmendTrade, AmendTradeError, ApiKey, Asset, Balance, L3Snapshot, MarketState,
+    MessagePort, OpenOrder, Order, OrderId, ReadL3, RevokeApiKey, Side, TradeAmendment,
+};
+
+/// A command which is processed by the matcher
+#[derive(Debug)]
+pub enum Command {
+    Open(MessagePort<OpenOrder, Order>),
+    Cancel(MessagePort<CancelOrders, Vec<OrderId>>),
+    ChangeState(MessagePort<ChangeState, MarketState>),
+    AdjustBalance(MessagePort<AdjustBalance, Balance>),
+    Snapshot(MessagePort<TakeSnapshot, anyhow::Result<usize>>),
+    Internals(MessagePort<ReadInternals, Internals>),
+    ReadL3(MessagePort<ReadL3, L3Snapshot>),
+    CreateApiKey(MessagePort<ApiKey, ApiKey>),
+    RevokeApiKey(MessagePort<RevokeApiKey, Option<ApiKey>>),
+    /// Registers an account, unless the user ID is taken
+    Register(MessagePort<Account, Option<Account>>),
+    Batch(MessagePort<Vec<BatchItem>, Vec<BatchResult>>),
+    /// Amends a trade, unless the trade archive fails to read it
+    AmendTrade(MessagePort<AmendTrade, anyhow::Result<Result<TradeAmendment, AmendTradeError>>>),
+}
+
+impl From<MessagePort<OpenOrder, Order>> for Command {
+    fn from(port: MessagePort<OpenOrder, Order>) -> Self {
+        Command::Open(port)
+    }
+}
+
+impl From<MessagePort<CancelOrders, Vec<OrderId>>> for Command {
+    fn from(port: MessagePort<CancelOrders, Vec<OrderId>>) -> Self {
+        Command::Cancel(port)
+    }
+}
+
+impl From<MessagePort<ChangeState, MarketState>> for Command {
+    fn from(port: MessagePort<ChangeState, MarketState>) -> Self {
+        Command::ChangeState(port)
+    }
+}
+
+impl From<MessagePort<AdjustBalance, Balance>> for Command {
+    fn from(port: MessagePort<AdjustBalance, Balance>) -> Self {
+        Command::AdjustBalance(port)
+    }
+}
+
+impl From<MessagePort<TakeSnapshot, anyhow::Result<usize>>> for Command {
+    fn from(port: MessagePort<TakeSnapshot, anyhow::Result<usize>>) -> Self {
+        Command::Snapshot(port)
+    }
+}
+
+impl From<MessagePort<ReadInternals, Internals>> for Command {
+    fn from(port: MessagePort<ReadInternals, Internals>) -> Self {
+        Command::Internals(port)
+    }
+}
+
+impl From<MessagePort<ReadL3, L3Snapshot>> for Command {
+    fn from(port: MessagePort<ReadL3, L3Snapshot>) -> Self {
+        Command::ReadL3(port)
+    }
+}
+
+impl From<MessagePort<ApiKey, ApiKey>> for Command {
+    fn from(port: MessagePort<ApiKey, ApiKey>) -> Self {
+        Command::CreateApiKey(port)
+    }
+}
+
+impl From<MessagePort<RevokeApiKey, Option<ApiKey>>> for Command {
+    fn from(port: MessagePort<RevokeApiKey, Option<ApiKey>>) -> Self {
+        Command::RevokeApiKey(port)
+    }
+}
+
+impl From<MessagePort<Account, Option<Account>>> for Command {
+    fn from(port: MessagePort<Account, Option<Account>>) -> Self {
+        Command::Register(port)
+    }
+}
+
+impl From<MessagePort<Vec<BatchItem>, Vec<BatchResult>>> for Command {
+    fn from(port: MessagePort<Vec<BatchItem>, Vec<BatchResult>>) -> Self {
+        Command::Batch(port)
+    }
+}
+
+impl From<MessagePort<AmendTrade, anyhow::Result<Result<TradeAmendment, AmendTradeError>>>>
+    for Command
+{
+    fn from(
+        port: MessagePort<AmendTrade, anyhow::Result<Result<TradeAmendment, AmendTradeError>>>,
+    ) -> Self {
+        Command::AmendTrade(port)
+    }
+}
+
+/// An item of a batch, which the matcher processes back-to-back with the others
+#[derive(Debug, PartialEq, Eq)]
+pub enum BatchItem {
+    Open(OpenOrder),
+    Cancel(CancelOrders),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchResult {
+    Open(Order),
+    Cancel(Vec<OrderId>),
+}
+
+/// Cancels all resting orders matching the filter in one step
+///
+/// Filters which are not set match all orders, the price range includes its bounds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CancelOrders {
+    pub user_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<OrderId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub side: Option<Side>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_price: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_price: Option<Decimal>,
+}
+
+impl CancelOrders {
+    /// Cancels all resting orders of a user
+    pub fn of_user(user_id: String) -> Self {
+        Self {
+            user_id: Some(user_id),
+            ..Self::default()
+        }
+    }
+
+    pub fn matches(&self, order: &Order) -> bool {
+        self.user_id
+            .as_ref()
+            .map_or(true, |user_id| &order.user_id == user_id)
+            && self.order_id.map_or(true, |order_id| order.id == order_id)
+            && self
+                .client_order_id
+                .as_ref()
+                .map_or(true, |id| order.client_order_id.as_ref() == Some(id))
+            && self.side.map_or(true, |side| order.side == side)
+            && self.min_price.map_or(true, |price| order.price >= price)
+            && self.max_price.map_or(true, |price| order.price <= price)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeState {
+    /// Halts trading until it is resumed
+    Halt,
+    /// Moves into the scheduled state
+    Resume,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdjustBalance {
+    pub user_id: String,
+    pub asset: Asset,
+    pub amount: Decimal,
+}
+
+/// Stores a snapshot of the engine and returns the number of log entries it covers
+#[derive(Debug)]
+pub struct TakeSnapshot;
+
+#[derive(Debug)]
+pub struct ReadInternals;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Internals {
+    pub market_state: MarketState,
+    pub market_state_until: Option<u128>,
+    pub resting_orders: usize,
+    pub last_order_id: u64,
+    pub engine_sequence: u64,
+    pub wal_entries: usize,
+    pub users: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn should_match_orders_of_the_filter() {
+        let order = Order::open_limit(OrderId(1), Side::Buy, dec!(10), dec!(100))
+            .with_user_id("alice".into());
+
+        assert!(CancelOrders::default().matches(&order));
+        assert!(CancelOrders::of_user("alice".into()).matches(&order));
+        assert!(!CancelOrders::of_user("bob".into()).matches(&order));
+
+        let filter = CancelOrders {
+            side: Some(Side::Buy),
+            min_price: Some(dec!(10)),
+            max_price: Some(dec!(12)),
+            ..CancelOrders::of_user("alice".into())
+        };
+        assert!(filter.matches(&order));
+
+        let filter = CancelOrders {
+            order_id: Some(OrderId(2)),
+            ..CancelOrders::default()
+        };
+        assert!(!filter.matches(&order));
+
+        let filter = CancelOrders {
+            client_order_id: Some("a-1".into()),
+            ..CancelOrders::default()
+        };
+        assert!(!filter.matches(&order));
+        assert!(filter.matches(&order.clone().with_client_order_id(Some("a-1".into()))));
+
+        let filter = CancelOrders {
+            side: Some(Side::Sell),
+            ..CancelOrders::default()
+        };
+        assert!(!filter.matches(&order));
+
+        let filter = CancelOrders {
+            max_price: Some(dec!(9)),
+            ..CancelOrders::default()
+        };
+        assert!(!filter.matches(&order));
+    }
+}
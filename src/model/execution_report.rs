@@ -0,0 +1,213 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::model::{Order, OrderId, OrderStatus, OrderType, Side, Trade, TradeId};
+
+/// The kind of change an execution report announces
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExecType {
+    Accepted,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Rejected,
+    Expired,
+}
+
+impl From<OrderStatus> for ExecType {
+    fn from(status: OrderStatus) -> Self {
+        match status {
+            OrderStatus::Open => ExecType::Accepted,
+            OrderStatus::PartiallyFilled => ExecType::PartiallyFilled,
+            OrderStatus::Filled => ExecType::Filled,
+            OrderStatus::Cancelled
+            | OrderStatus::CancelledNoLiquidity
+            | OrderStatus::CancelledPriceBand => ExecType::Cancelled,
+            OrderStatus::Rejected => ExecType::Rejected,
+            OrderStatus::Expired => ExecType::Expired,
+        }
+    }
+}
+
+/// A change of an order, which is only sent to its owner
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionReport {
+    /// Increases by one with every report of the user, so gaps can be detected
+    pub sequence: u64,
+    /// The engine sequence of the event which caused the report
+    pub engine_sequence: u64,
+    pub exec_type: ExecType,
+    pub order_id: OrderId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+    pub side: Side,
+    pub order_type: OrderType,
+    pub status: OrderStatus,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub filled: Decimal,
+    /// The trade, price and quantity of the fill which caused the report
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trade_id: Option<TradeId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_price: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_quantity: Option<Decimal>,
+    pub transact_time: u128,
+    #[serde(skip)]
+    pub user_id: String,
+}
+
+/// Creates the execution reports of the working orders
+///
+/// The orders are tracked from their acceptance until they are filled,
+/// cancelled or expired, so each fill reports the state after it.
+#[derive(Debug, Default)]
+pub struct ExecutionReports {
+    /// The last sequence number of each user
+    sequences: HashMap<String, u64>,
+    orders: HashMap<OrderId, Order>,
+}
+
+impl ExecutionReports {
+    /// Restores the sequence numbers and the resting orders
+    pub fn restore(&mut self, sequences: HashMap<String, u64>, orders: Vec<Order>) {
+        self.sequences = sequences;
+        self.orders = orders.into_iter().map(|order| (order.id, order)).collect();
+    }
+
+    pub fn sequences(&self) -> &HashMap<String, u64> {
+        &self.sequences
+    }
+
+    /// Reports a processed order as accepted before its fills, or as rejected
+    pub fn accept(&mut self, order: &Order, now: u128) -> ExecutionReport {
+        if order.status == OrderStatus::Rejected {
+            return self.report(order, None, now);
+        }
+
+        let accepted = Order {
+            status: OrderStatus::Open,
+            filled: Decimal::ZERO,
+            ..order.clone()
+        };
+        let report = self.report(&accepted, None, now);
+        self.orders.insert(accepted.id, accepted);
+        report
+    }
+
+    /// Reports the fill of both orders of a trade
+    pub fn fill(&mut self, trade: &Trade) -> Vec<ExecutionReport> {
+        let mut reports = Vec::new();
+        for order_id in [trade.buy_order_id, trade.sell_order_id] {
+            let Some(mut order) = self.orders.remove(&order_id) else {
+                continue;
+            };
+            order.fill(trade.quantity);
+            order.engine_sequence = trade.engine_sequence;
+            reports.push(self.report(&order, Some(trade), trade.executed_at));
+            if !order.is_filled() {
+                self.orders.insert(order_id, order);
+            }
+        }
+        reports
+    }
+
+    /// Reports an order which was cancelled or expired
+    pub fn close(&mut self, order: &Order, now: u128) -> ExecutionReport {
+        self.orders.remove(&order.id);
+        self.report(order, None, now)
+    }
+
+    fn report(&mut self, order: &Order, fill: Option<&Trade>, now: u128) -> ExecutionReport {
+        let sequence = self.sequences.entry(order.user_id.clone()).or_default();
+        *sequence += 1;
+
+        ExecutionReport {
+            sequence: *sequence,
+            engine_sequence: order.engine_sequence,
+            exec_type: order.status.into(),
+            order_id: order.id,
+            client_order_id: order.client_order_id.clone(),
+            side: order.side,
+            order_type: order.order_type,
+            status: order.status,
+            price: order.price,
+            quantity: order.quantity,
+            filled: order.filled,
+            trade_id: fill.map(|trade| trade.id),
+            last_price: fill.map(|trade| trade.price),
+            last_quantity: fill.map(|trade| trade.quantity),
+            transact_time: now,
+            user_id: order.user_id.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn should_report_the_lifecycle_of_orders() {
+        let mut reports = ExecutionReports::default();
+        let mut ask = Order::open_limit(OrderId(1), Side::Sell, dec!(10), dec!(100))
+            .with_user_id("alice".into());
+        let mut bid =
+            Order::open_limit(OrderId(2), Side::Buy, dec!(10), dec!(40)).with_user_id("bob".into());
+
+        let report = reports.accept(&ask, 0);
+        assert_eq!((report.sequence, report.exec_type), (1, ExecType::Accepted));
+
+        let filled = ask.fill(bid.fill(dec!(40)));
+        let trade = Trade::new(dec!(10), filled, &bid, &ask)
+            .with_id(TradeId(1))
+            .with_engine_sequence(3);
+        let report = reports.accept(&bid, 0);
+        assert_eq!(
+            (report.sequence, report.exec_type, report.filled),
+            (1, ExecType::Accepted, dec!(0))
+        );
+
+        let fills = reports.fill(&trade);
+        assert_eq!(fills[0].user_id, "bob");
+        assert_eq!(
+            (
+                fills[0].sequence,
+                fills[0].exec_type,
+                fills[0].last_quantity
+            ),
+            (2, ExecType::Filled, Some(dec!(40)))
+        );
+        assert_eq!(
+            (fills[0].engine_sequence, fills[0].trade_id),
+            (3, Some(TradeId(1)))
+        );
+        assert_eq!(fills[1].user_id, "alice");
+        assert_eq!(
+            (fills[1].sequence, fills[1].exec_type, fills[1].filled),
+            (2, ExecType::PartiallyFilled, dec!(40))
+        );
+
+        ask.cancel();
+        let report = reports.close(&ask, 0);
+        assert_eq!(
+            (report.sequence, report.exec_type),
+            (3, ExecType::Cancelled)
+        );
+        assert!(reports.orders.is_empty());
+    }
+
+    #[test]
+    fn should_not_track_rejected_orders() {
+        let mut reports = ExecutionReports::default();
+        let mut order = Order::open_market(OrderId(1), Side::Buy, dec!(10));
+        order.reject();
+
+        let report = reports.accept(&order, 0);
+        assert_eq!(report.exec_type, ExecType::Rejected);
+        assert!(reports.orders.is_empty());
+    }
+}
@@ -1,4 +1,9 @@
-use crate::model::{Order, OrderBookSide, OrderType, Side, Trade};
+use rust_decimal::Decimal;
+
+use crate::model::{
+    Equilibrium, L3Order, Order, OrderBookSide, OrderType, PriceBand, PricePair, Side, TimeInForce,
+    Trade,
+};
 
 #[derive(Debug)]
 pub struct Market {
@@ -13,17 +18,130 @@ impl Market {
         Self { bids, asks }
     }
 
-    pub fn push(&mut self, order: &mut Order) -> Vec<Trade> {
-        let trades = self.fill_order(order);
-        if !order.is_filled() && order.order_type == OrderType::Limit {
-            self.push_order(order.clone());
+    /// Matches an order against the book without executing trades outside the band
+    ///
+    /// If the order would still cross the book beyond the band, its remainder
+    /// is cancelled so the caller can halt trading.
+    pub fn push(&mut self, order: &mut Order, band: Option<PriceBand>) -> Vec<Trade> {
+        let trades = self.fill_order(order, band);
+        if !order.is_filled() {
+            let crosses_band = self
+                .best_price(!order.side)
+                .map_or(false, |best_price| order.crosses(best_price));
+            match order.order_type {
+                _ if crosses_band => order.cancel_price_band(),
+                OrderType::Limit => self.push_order(order.clone()),
+                OrderType::Market => order.cancel_no_liquidity(),
+            }
+        }
+        trades
+    }
+
+    /// Collects an order for a call auction without matching it
+    ///
+    /// Only limit orders take part in an auction, market orders are rejected.
+    pub fn push_auction(&mut self, order: &mut Order) {
+        match order.order_type {
+            OrderType::Limit => self.push_order(order.clone()),
+            OrderType::Market => order.reject(),
+        }
+    }
+
+    /// Returns the price an auction would currently be uncrossed at
+    pub fn equilibrium(&self, reference: Option<Decimal>) -> Option<Equilibrium> {
+        Equilibrium::find(&self.bids.depth(), &self.asks.depth(), reference)
+    }
+
+    /// Executes all crossing orders at the single auction price
+    pub fn uncross(&mut self, price: Decimal) -> Vec<Trade> {
+        let mut trades = Vec::new();
+
+        while self.bids.best_price().map_or(false, |bid| bid >= price)
+            && self.asks.best_price().map_or(false, |ask| ask <= price)
+        {
+            let (Some(mut bid), Some(mut ask)) = (self.bids.pop_best(), self.asks.pop_best())
+            else {
+                break;
+            };
+
+            let used_qty = bid.fill(ask.unfilled());
+            ask.fill(used_qty);
+            trades.push(Trade::new(price, used_qty, &bid, &ask));
+
+            if !bid.is_filled() {
+                self.bids.push_best(bid);
+            }
+            if !ask.is_filled() {
+                self.asks.push_best(ask);
+            }
         }
+
         trades
     }
 
-    fn fill_order(&mut self, order: &mut Order) -> Vec<Trade> {
+    /// Removes all day orders from the book and returns them expired
+    pub fn expire_day_orders(&mut self) -> Vec<Order> {
+        let is_day_order = |order: &Order| order.time_in_force == TimeInForce::Day;
+        let mut expired = self.bids.remove_where(is_day_order);
+        expired.extend(self.asks.remove_where(is_day_order));
+        for order in expired.iter_mut() {
+            order.expire();
+        }
+        expired
+    }
+
+    /// Removes all orders matching the predicate from the book and returns them cancelled
+    pub fn cancel_where<P>(&mut self, predicate: P) -> Vec<Order>
+    where
+        P: Fn(&Order) -> bool,
+    {
+        let mut cancelled = self.bids.remove_where(&predicate);
+        cancelled.extend(self.asks.remove_where(&predicate));
+        for order in cancelled.iter_mut() {
+            order.cancel();
+        }
+        cancelled
+    }
+
+    /// Returns all resting orders, each side in priority order
+    pub fn orders(&self) -> Vec<Order> {
+        let mut orders = self.bids.orders();
+        orders.extend(self.asks.orders());
+        orders
+    }
+
+    pub fn resting_orders(&self) -> usize {
+        self.bids.order_count() + self.asks.order_count()
+    }
+
+    /// Puts a resting order back into the book without matching it
+    pub fn restore(&mut self, order: Order) {
+        self.push_order(order);
+    }
+
+    /// Aggregates the resting orders of a side into price levels
+    pub fn depth(&self, side: Side) -> Vec<PricePair> {
+        self.side(side).depth()
+    }
+
+    /// Returns the resting orders of a side in priority order
+    pub fn queue(&self, side: Side) -> Vec<L3Order> {
+        self.side(side).queue()
+    }
+
+    /// Returns the number of orders resting at a price of a side
+    pub fn level_len(&self, side: Side, price: Decimal) -> usize {
+        self.side(side).level_len(price)
+    }
+
+    /// Returns the best price currently resting on the given side
+    pub fn best_price(&self, side: Side) -> Option<Decimal> {
+        self.side(side).best_price()
+    }
+
+    fn fill_order(&mut self, order: &mut Order, band: Option<PriceBand>) -> Vec<Trade> {
         let opposite_side = self.side_mut(!order.side);
-        opposite_side.fill(order)
+        opposite_side.fill(order, band)
     }
 
     fn push_order(&mut self, order: Order) {
@@ -31,6 +149,13 @@ impl Market {
         order_side.push(order);
     }
 
+    fn side(&self, side: Side) -> &OrderBookSide {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        }
+    }
+
     fn side_mut(&mut self, side: Side) -> &mut OrderBookSide {
         match side {
             Side::Buy => &mut self.bids,
@@ -50,7 +175,7 @@ mod tests {
         let mut market = Market::new();
 
         let mut o = Order::open_limit(OrderId(1), Side::Buy, dec!(10), dec!(100));
-        market.push(&mut o);
+        market.push(&mut o, None);
 
         assert_eq!(market.asks.len(), 0);
         assert_eq!(market.bids.len(), 1);
@@ -65,7 +190,7 @@ mod tests {
 
         let mut o = Order::open_limit(OrderId(1), Side::Sell, dec!(10), dec!(100));
 
-        let trades = market.push(&mut o);
+        let trades = market.push(&mut o, None);
         assert!(trades.is_empty());
 
         assert_eq!(market.bids.len(), 0);
@@ -86,24 +211,48 @@ mod tests {
             dec!(10),
             dec!(100),
         );
-        market.push(&mut o);
+        market.push(&mut o, None);
 
         let mut o = Order::open(OrderId(1), Side::Buy, OrderType::Limit, dec!(10), dec!(100));
-        market.push(&mut o);
+        market.push(&mut o, None);
 
         assert_eq!(market.bids.len(), 0);
         assert_eq!(market.asks.len(), 0);
     }
 
+    #[test]
+    fn should_queue_orders_by_priority() {
+        let mut market = Market::new();
+
+        let mut o = Order::open_limit(OrderId(1), Side::Sell, dec!(11), dec!(100));
+        market.push(&mut o, None);
+        let mut o = Order::open_limit(OrderId(2), Side::Sell, dec!(10), dec!(100));
+        market.push(&mut o, None);
+        let mut o = Order::open_limit(OrderId(3), Side::Sell, dec!(10), dec!(50));
+        market.push(&mut o, None);
+
+        let queue = market.queue(Side::Sell);
+        let queue = queue
+            .iter()
+            .map(|order| (order.order_id, order.position))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            queue,
+            vec![(OrderId(2), 0), (OrderId(3), 1), (OrderId(1), 0)]
+        );
+        assert_eq!(market.level_len(Side::Sell, dec!(10)), 2);
+        assert_eq!(market.level_len(Side::Buy, dec!(10)), 0);
+    }
+
     #[test]
     fn should_handle_partial_fill() {
         let mut matcher = Market::new();
 
         let mut o = Order::open_limit(OrderId(1), Side::Sell, dec!(10), dec!(100));
-        matcher.push(&mut o);
+        matcher.push(&mut o, None);
 
         let mut o = Order::open_limit(OrderId(1), Side::Buy, dec!(10), dec!(145));
-        matcher.push(&mut o);
+        matcher.push(&mut o, None);
 
         assert!(matcher.asks.is_empty());
         assert!(!matcher.bids.is_empty());
@@ -119,6 +268,11 @@ mod tests {
                 quantity: dec!(145),
                 filled: dec!(100),
                 created_at: bid.created_at,
+                protection_price: None,
+                time_in_force: TimeInForce::GoodTillCancelled,
+                client_order_id: None,
+                engine_sequence: 0,
+                user_id: String::new(),
             }],
         );
     }
@@ -128,12 +282,170 @@ mod tests {
         let mut market = Market::new();
 
         let mut o = Order::open_limit(OrderId(1), Side::Sell, dec!(10), dec!(100));
-        market.push(&mut o);
+        market.push(&mut o, None);
 
         let mut o = Order::open_market(OrderId(1), Side::Buy, dec!(145));
-        market.push(&mut o);
+        market.push(&mut o, None);
 
         assert!(market.asks.is_empty());
         assert!(market.bids.is_empty());
+        assert_eq!(o.filled, dec!(100));
+        assert_eq!(o.status, OrderStatus::CancelledNoLiquidity);
+    }
+
+    #[test]
+    fn should_stop_market_order_at_protection_price() {
+        let mut market = Market::new();
+
+        let mut o = Order::open_limit(OrderId(1), Side::Sell, dec!(10), dec!(100));
+        market.push(&mut o, None);
+        let mut o = Order::open_limit(OrderId(2), Side::Sell, dec!(11), dec!(100));
+        market.push(&mut o, None);
+        let mut o = Order::open_limit(OrderId(3), Side::Sell, dec!(20), dec!(100));
+        market.push(&mut o, None);
+        assert_eq!(market.best_price(Side::Sell), Some(dec!(10)));
+
+        let mut o = Order::open_market(OrderId(4), Side::Buy, dec!(300))
+            .with_protection_price(Some(dec!(11)));
+        let trades = market.push(&mut o, None);
+
+        assert_eq!(trades.len(), 2);
+        assert!(trades
+            .iter()
+            .all(|trade| trade.taker_side == Some(Side::Buy)));
+        assert_eq!(o.filled, dec!(200));
+        assert_eq!(o.status, OrderStatus::CancelledNoLiquidity);
+        assert!(market.bids.is_empty());
+        assert_eq!(market.best_price(Side::Sell), Some(dec!(20)));
+    }
+
+    #[test]
+    fn should_collect_orders_in_auction() {
+        let mut market = Market::new();
+
+        let mut o = Order::open_limit(OrderId(1), Side::Sell, dec!(10), dec!(100));
+        market.push_auction(&mut o);
+        let mut o = Order::open_limit(OrderId(2), Side::Buy, dec!(12), dec!(50));
+        market.push_auction(&mut o);
+        let mut o = Order::open_market(OrderId(3), Side::Buy, dec!(50));
+        market.push_auction(&mut o);
+
+        assert_eq!(o.status, OrderStatus::Rejected);
+        assert_eq!(market.bids.len(), 1);
+        assert_eq!(market.asks.len(), 1);
+    }
+
+    #[test]
+    fn should_uncross_at_a_single_price() {
+        let mut market = Market::new();
+
+        let mut o = Order::open_limit(OrderId(1), Side::Sell, dec!(10), dec!(100));
+        market.push_auction(&mut o);
+        let mut o = Order::open_limit(OrderId(2), Side::Sell, dec!(11), dec!(100));
+        market.push_auction(&mut o);
+        let mut o = Order::open_limit(OrderId(3), Side::Buy, dec!(12), dec!(150));
+        market.push_auction(&mut o);
+        let mut o = Order::open_limit(OrderId(4), Side::Buy, dec!(9), dec!(100));
+        market.push_auction(&mut o);
+
+        let equilibrium = market.equilibrium(None).unwrap();
+        assert_eq!(equilibrium.price, dec!(11));
+        assert_eq!(equilibrium.volume, dec!(150));
+
+        let trades = market.uncross(equilibrium.price);
+        assert_eq!(trades.len(), 2);
+        assert!(trades.iter().all(|trade| trade.price == dec!(11)));
+        assert!(trades.iter().all(|trade| trade.taker_side.is_none()));
+        assert_eq!(trades[0].sell_order_id, OrderId(1));
+        assert_eq!(trades[1].sell_order_id, OrderId(2));
+        assert_eq!(
+            market.depth(Side::Buy),
+            vec![PricePair::new(dec!(9), dec!(100))]
+        );
+        assert_eq!(
+            market.depth(Side::Sell),
+            vec![PricePair::new(dec!(11), dec!(50))]
+        );
+    }
+
+    #[test]
+    fn should_expire_day_orders() {
+        let mut market = Market::new();
+
+        let mut o = Order::open_limit(OrderId(1), Side::Sell, dec!(10), dec!(100))
+            .with_time_in_force(TimeInForce::Day);
+        market.push(&mut o, None);
+        let mut o = Order::open_limit(OrderId(2), Side::Sell, dec!(10), dec!(100));
+        market.push(&mut o, None);
+        let mut o = Order::open_limit(OrderId(3), Side::Buy, dec!(9), dec!(100))
+            .with_time_in_force(TimeInForce::Day);
+        market.push(&mut o, None);
+
+        let expired = market.expire_day_orders();
+        assert_eq!(expired.len(), 2);
+        assert!(expired
+            .iter()
+            .all(|order| order.status == OrderStatus::Expired));
+        assert!(market.bids.is_empty());
+        assert_eq!(market.asks.peek().unwrap().id, OrderId(2));
+    }
+
+    #[test]
+    fn should_cancel_orders_of_a_user() {
+        let mut market = Market::new();
+
+        let mut o = Order::open_limit(OrderId(1), Side::Sell, dec!(10), dec!(100))
+            .with_user_id("alice".into());
+        market.push(&mut o, None);
+        let mut o = Order::open_limit(OrderId(2), Side::Sell, dec!(10), dec!(100))
+            .with_user_id("bob".into());
+        market.push(&mut o, None);
+        let mut o = Order::open_limit(OrderId(3), Side::Buy, dec!(9), dec!(100))
+            .with_user_id("alice".into());
+        market.push(&mut o, None);
+
+        let cancelled = market.cancel_where(|order| order.user_id == "alice");
+        assert_eq!(cancelled.len(), 2);
+        assert!(cancelled
+            .iter()
+            .all(|order| order.status == OrderStatus::Cancelled));
+        assert_eq!(market.orders().len(), 1);
+        assert_eq!(market.orders()[0].id, OrderId(2));
+    }
+
+    #[test]
+    fn should_cancel_order_crossing_beyond_price_band() {
+        let mut market = Market::new();
+
+        let mut o = Order::open_limit(OrderId(1), Side::Sell, dec!(10), dec!(100));
+        market.push(&mut o, None);
+        let mut o = Order::open_limit(OrderId(2), Side::Sell, dec!(15), dec!(100));
+        market.push(&mut o, None);
+
+        let band = PriceBand::around(dec!(10), dec!(0.1));
+        let mut o = Order::open_limit(OrderId(3), Side::Buy, dec!(15), dec!(200));
+        let trades = market.push(&mut o, Some(band));
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(o.filled, dec!(100));
+        assert_eq!(o.status, OrderStatus::CancelledPriceBand);
+        assert!(market.bids.is_empty());
+        assert_eq!(market.best_price(Side::Sell), Some(dec!(15)));
+    }
+
+    #[test]
+    fn should_rest_order_within_price_band() {
+        let mut market = Market::new();
+
+        let mut o = Order::open_limit(OrderId(1), Side::Sell, dec!(15), dec!(100));
+        market.push(&mut o, None);
+
+        let band = PriceBand::around(dec!(10), dec!(0.1));
+        let mut o = Order::open_limit(OrderId(2), Side::Buy, dec!(10), dec!(100));
+        let trades = market.push(&mut o, Some(band));
+
+        assert!(trades.is_empty());
+        assert_eq!(o.status, OrderStatus::Open);
+        assert_eq!(market.bids.len(), 1);
     }
 }
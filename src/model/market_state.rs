@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarketState {
+    /// Orders are collected without matching or an indicative price
+    PreOpen,
+    /// Orders are collected for a call auction
+    Auction,
+    #[default]
+    Continuous,
+    Halted,
+    Closed,
+}
+
+impl MarketState {
+    /// Returns whether moving into this state uncrosses the orders collected before
+    ///
+    /// A halt keeps them unmatched until trading resumes.
+    pub fn uncrosses(&self) -> bool {
+        matches!(self, MarketState::Continuous | MarketState::Closed)
+    }
+}
+
+impl FromStr for MarketState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PreOpen" => Ok(MarketState::PreOpen),
+            "Auction" => Ok(MarketState::Auction),
+            "Continuous" => Ok(MarketState::Continuous),
+            "Halted" => Ok(MarketState::Halted),
+            "Closed" => Ok(MarketState::Closed),
+            _ => Err(format!("unknown market state {s}")),
+        }
+    }
+}
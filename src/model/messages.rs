@@ -21,11 +21,11 @@ where
         Self { receiver, port }
     }
 
-    pub async fn send_to(
-        self,
-        target: &mpsc::Sender<MessagePort<Req, Res>>,
-    ) -> anyhow::Result<Res> {
-        target.send(self.port).await?;
+    pub async fn send_to<T>(self, target: &mpsc::Sender<T>) -> anyhow::Result<Res>
+    where
+        T: From<MessagePort<Req, Res>> + Send + Sync + Debug + 'static,
+    {
+        target.send(self.port.into()).await?;
         let response = self.receiver.await?;
         Ok(response)
     }
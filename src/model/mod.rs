@@ -1,35 +1,166 @@
 use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
+pub use account::Account;
+pub use api_key::{ApiKey, RevokeApiKey, Scope};
+pub use auction::Equilibrium;
+pub use balance::{Asset, Balance};
+pub use candles::{Candle, Candles, Interval};
+pub use circuit_breaker::{CircuitBreaker, PriceBand, PriceHistory};
+pub use client_orders::ClientOrders;
+pub use command::{
+    AdjustBalance, BatchItem, BatchResult, CancelOrders, ChangeState, Command, Internals,
+    ReadInternals, TakeSnapshot,
+};
+pub use execution_report::{ExecutionReport, ExecutionReports};
 pub use market::Market;
+pub use market_state::MarketState;
 pub use messages::{MessageChannel, MessagePort};
 pub use order::{Order, OrderId, OrderStatus};
-pub use order_book::{OrderBook, PricePair};
+pub use order_book::{BookView, OrderBook, PricePair};
 pub use order_book_side::OrderBookSide;
+pub use order_feed::{L3Change, L3Event, L3Order, L3Snapshot, ReadL3};
 pub use order_type::OrderType;
+pub use schedule::Schedule;
 pub use side::Side;
+pub use snapshot::Snapshot;
 pub use state::State;
-pub use trade::Trade;
-pub use user::User;
-pub use wal::WriteAheadLog;
+pub use ticker::{Ticker, TickerStats};
+pub use time_in_force::TimeInForce;
+pub use trade::{Trade, TradeId};
+pub use trade_amendment::{AmendTrade, AmendTradeError, Amendment, TradeAmendment};
+pub use trade_archive::{ArchiveReader, TradeArchive};
+pub use trade_history::{TapeEvent, TapeTrade, TradeHistory, TradePage, MAX_TRADE_PAGE};
+pub use user::{User, ADMIN_ROLE};
+pub use wal::{WalEntry, WalEvent, WriteAheadLog};
 
+mod account;
+mod api_key;
+mod auction;
+mod balance;
+mod candles;
+mod circuit_breaker;
+mod client_orders;
+mod command;
 mod compare;
+mod execution_report;
 mod market;
+mod market_state;
 mod messages;
 mod order;
 mod order_book;
 mod order_book_side;
+mod order_feed;
 mod order_type;
+mod schedule;
 mod side;
+mod snapshot;
 mod state;
+mod ticker;
+mod time_in_force;
 mod trade;
+mod trade_amendment;
+mod trade_archive;
+mod trade_history;
 mod user;
 mod wal;
 
+const MAX_CLIENT_ORDER_ID_LEN: usize = 64;
+
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OpenOrder {
     pub quantity: Decimal,
     pub price: Decimal,
     pub side: Side,
     pub order_type: OrderType,
+    #[serde(default)]
+    pub protection_price: Option<Decimal>,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    /// An ID of the client, which returns the existing order when it is submitted again
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+    /// The owner of the order, which is taken from the authenticated user
+    #[serde(skip)]
+    pub user_id: String,
+}
+
+impl OpenOrder {
+    /// Checks the order before it is sent to the matcher
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.quantity <= Decimal::ZERO {
+            return Err("quantity must be positive");
+        }
+        if self.order_type == OrderType::Limit && self.price <= Decimal::ZERO {
+            return Err("price must be positive");
+        }
+        if self
+            .protection_price
+            .map_or(false, |price| price <= Decimal::ZERO)
+        {
+            return Err("protection price must be positive");
+        }
+        if let Some(client_order_id) = &self.client_order_id {
+            if client_order_id.is_empty() || client_order_id.len() > MAX_CLIENT_ORDER_ID_LEN {
+                return Err("client order ID must have between 1 and 64 characters");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Deserializes an optional time in nanoseconds since the epoch from a query string
+///
+/// Query strings are not parsed into 128-bit integers, so the time is read as 64 bits.
+pub fn deserialize_query_nanos<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u128>, D::Error> {
+    Ok(Option::<u64>::deserialize(deserializer)?.map(u128::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn open_order(order_type: OrderType, price: Decimal, quantity: Decimal) -> OpenOrder {
+        OpenOrder {
+            quantity,
+            price,
+            side: Side::Buy,
+            order_type,
+            protection_price: None,
+            time_in_force: TimeInForce::default(),
+            client_order_id: None,
+            user_id: String::new(),
+        }
+    }
+
+    #[test]
+    fn should_validate_an_order() {
+        assert!(open_order(OrderType::Limit, dec!(10), dec!(1))
+            .validate()
+            .is_ok());
+        assert!(open_order(OrderType::Market, dec!(0), dec!(1))
+            .validate()
+            .is_ok());
+        assert!(open_order(OrderType::Limit, dec!(0), dec!(1))
+            .validate()
+            .is_err());
+        assert!(open_order(OrderType::Limit, dec!(10), dec!(-1))
+            .validate()
+            .is_err());
+
+        let order = OpenOrder {
+            protection_price: Some(dec!(0)),
+            ..open_order(OrderType::Market, dec!(0), dec!(1))
+        };
+        assert!(order.validate().is_err());
+
+        let order = OpenOrder {
+            client_order_id: Some(String::new()),
+            ..open_order(OrderType::Limit, dec!(10), dec!(1))
+        };
+        assert!(order.validate().is_err());
+    }
 }
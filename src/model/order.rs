@@ -5,9 +5,9 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::model::side::Side;
 
-use super::OrderType;
+use super::{OrderType, TimeInForce};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct OrderId(pub u64);
 
 impl Add<u64> for OrderId {
@@ -23,6 +23,11 @@ pub enum OrderStatus {
     Open,
     PartiallyFilled,
     Filled,
+    Cancelled,
+    CancelledNoLiquidity,
+    CancelledPriceBand,
+    Rejected,
+    Expired,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -36,6 +41,21 @@ pub struct Order {
     pub quantity: Decimal,
     pub filled: Decimal,
     pub created_at: u128,
+    #[serde(default)]
+    pub protection_price: Option<Decimal>,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    #[serde(default)]
+    pub user_id: String,
+    /// The ID the client chose for the order, which is unique per user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+    /// The global sequence of the latest event which changed the order
+    ///
+    /// Accepting an order, each trade and each cancellation or expiry increases
+    /// the engine sequence by one, which orders all events of the engine.
+    #[serde(default)]
+    pub engine_sequence: u64,
 }
 
 impl Order {
@@ -69,21 +89,50 @@ impl Order {
             quantity,
             filled,
             created_at,
+            protection_price: None,
+            time_in_force: TimeInForce::default(),
+            user_id: String::new(),
+            client_order_id: None,
+            engine_sequence: 0,
         }
     }
 
+    pub fn with_user_id(mut self, user_id: String) -> Self {
+        self.user_id = user_id;
+        self
+    }
+
+    pub fn with_client_order_id(mut self, client_order_id: Option<String>) -> Self {
+        self.client_order_id = client_order_id;
+        self
+    }
+
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    pub fn with_protection_price(mut self, protection_price: Option<Decimal>) -> Self {
+        self.protection_price = protection_price;
+        self
+    }
+
     pub fn unfilled(&self) -> Decimal {
         self.quantity - self.filled
     }
 
     pub fn crosses(&self, other: Decimal) -> bool {
-        if self.order_type == OrderType::Market {
-            return true;
-        }
+        let limit = match self.order_type {
+            OrderType::Limit => self.price,
+            OrderType::Market => match self.protection_price {
+                Some(protection_price) => protection_price,
+                None => return true,
+            },
+        };
 
         match self.side {
-            Side::Buy => self.price >= other,
-            Side::Sell => self.price <= other,
+            Side::Buy => limit >= other,
+            Side::Sell => limit <= other,
         }
     }
 
@@ -102,6 +151,31 @@ impl Order {
         used
     }
 
+    /// Cancels the unfilled remainder of an order on request
+    pub fn cancel(&mut self) {
+        self.status = OrderStatus::Cancelled;
+    }
+
+    /// Cancels the unfilled remainder of an order which ran out of liquidity
+    pub fn cancel_no_liquidity(&mut self) {
+        self.status = OrderStatus::CancelledNoLiquidity;
+    }
+
+    /// Cancels the unfilled remainder of an order which would trade outside the price band
+    pub fn cancel_price_band(&mut self) {
+        self.status = OrderStatus::CancelledPriceBand;
+    }
+
+    /// Rejects an order without matching it
+    pub fn reject(&mut self) {
+        self.status = OrderStatus::Rejected;
+    }
+
+    /// Expires a day order when the market closes
+    pub fn expire(&mut self) {
+        self.status = OrderStatus::Expired;
+    }
+
     pub fn is_filled(&self) -> bool {
         self.status == OrderStatus::Filled
     }
@@ -155,6 +229,26 @@ mod tests {
         assert!(o1.crosses(o2.price));
     }
 
+    #[test]
+    fn should_always_cross_an_unprotected_market_order() {
+        let o = Order::open_market(OrderId(1), Side::Buy, dec!(500));
+
+        assert!(o.crosses(dec!(1_000_000)));
+    }
+
+    #[test]
+    fn should_not_cross_beyond_the_protection_price() {
+        let buy = Order::open_market(OrderId(1), Side::Buy, dec!(500))
+            .with_protection_price(Some(dec!(12)));
+        let sell = Order::open_market(OrderId(2), Side::Sell, dec!(500))
+            .with_protection_price(Some(dec!(12)));
+
+        assert!(buy.crosses(dec!(12)));
+        assert!(!buy.crosses(dec!(13)));
+        assert!(sell.crosses(dec!(12)));
+        assert!(!sell.crosses(dec!(11)));
+    }
+
     #[test]
     fn should_be_partially_filled() {
         let mut o = Order::open(OrderId(1), Side::Buy, OrderType::Limit, dec!(42), dec!(500));
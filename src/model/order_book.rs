@@ -2,10 +2,12 @@ use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
-use super::Side;
+use super::{Equilibrium, MarketState, Side};
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OrderBook {
+    pub state: MarketState,
+    pub indicative: Option<Equilibrium>,
     pub last: Option<Decimal>,
     pub best_bid: Option<Decimal>,
     pub best_ask: Option<Decimal>,
@@ -16,6 +18,8 @@ pub struct OrderBook {
 impl OrderBook {
     pub fn new() -> Self {
         Self {
+            state: MarketState::Continuous,
+            indicative: None,
             last: None,
             best_bid: None,
             best_ask: None,
@@ -24,6 +28,14 @@ impl OrderBook {
         }
     }
 
+    /// Replaces all price levels, best price first
+    pub fn replace(&mut self, bids: Vec<PricePair>, asks: Vec<PricePair>) {
+        self.best_bid = bids.first().map(|p| p.price);
+        self.best_ask = asks.first().map(|p| p.price);
+        self.bids = bids;
+        self.asks = asks;
+    }
+
     pub fn place(&mut self, side: Side, price: Decimal, qty: Decimal) {
         match side {
             Side::Buy => self.place_bid(price, qty),
@@ -123,6 +135,63 @@ impl OrderBook {
     }
 }
 
+/// Options which limit the price levels of an order book for a client
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookView {
+    /// The number of price levels per side
+    #[serde(default)]
+    pub depth: Option<usize>,
+    /// A price increment to merge the levels into, bids are rounded down and asks up
+    #[serde(default)]
+    pub grouping: Option<Decimal>,
+}
+
+impl BookView {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.depth == Some(0) {
+            return Err("depth must be positive");
+        }
+        if self
+            .grouping
+            .map_or(false, |grouping| grouping <= Decimal::ZERO)
+        {
+            return Err("grouping must be positive");
+        }
+        Ok(())
+    }
+
+    /// Returns the order book with grouped and limited price levels
+    ///
+    /// The best prices stay exact, only the levels are changed.
+    pub fn apply(&self, order_book: &OrderBook) -> OrderBook {
+        OrderBook {
+            bids: self.levels(&order_book.bids, Decimal::floor),
+            asks: self.levels(&order_book.asks, Decimal::ceil),
+            ..order_book.clone()
+        }
+    }
+
+    fn levels(&self, levels: &[PricePair], round: fn(&Decimal) -> Decimal) -> Vec<PricePair> {
+        let depth = self.depth.unwrap_or(usize::MAX);
+        let Some(grouping) = self.grouping else {
+            return levels.iter().take(depth).cloned().collect();
+        };
+
+        let mut grouped: Vec<PricePair> = Vec::new();
+        for level in levels {
+            let price = (round(&(level.price / grouping)) * grouping).normalize();
+            if let Some(last) = grouped.last_mut().filter(|last| last.price == price) {
+                last.quantity += level.quantity;
+            } else if grouped.len() == depth {
+                break;
+            } else {
+                grouped.push(PricePair::new(price, level.quantity));
+            }
+        }
+        grouped
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PricePair {
     pub price: Decimal,
@@ -140,6 +209,63 @@ mod tests {
     use super::*;
     use rust_decimal_macros::dec;
 
+    #[test]
+    fn should_limit_the_depth() {
+        let mut o = OrderBook::new();
+        o.place_bid(dec!(11), dec!(200));
+        o.place_bid(dec!(10), dec!(300));
+        o.place_ask(dec!(12), dec!(100));
+
+        let view = BookView {
+            depth: Some(1),
+            grouping: None,
+        };
+        let o = view.apply(&o);
+        assert_eq!(o.bids, vec![PricePair::new(dec!(11), dec!(200))]);
+        assert_eq!(o.asks, vec![PricePair::new(dec!(12), dec!(100))]);
+        assert_eq!(o.best_bid, Some(dec!(11)));
+    }
+
+    #[test]
+    fn should_group_price_levels() {
+        let mut o = OrderBook::new();
+        o.place_bid(dec!(10.7), dec!(200));
+        o.place_bid(dec!(10.2), dec!(300));
+        o.place_bid(dec!(9.9), dec!(50));
+        o.place_bid(dec!(8.5), dec!(10));
+        o.place_ask(dec!(11.1), dec!(100));
+        o.place_ask(dec!(11.5), dec!(100));
+        o.place_ask(dec!(12), dec!(100));
+
+        let view = BookView {
+            depth: Some(2),
+            grouping: Some(dec!(0.5)),
+        };
+        let o = view.apply(&o);
+        assert_eq!(
+            o.bids,
+            vec![
+                PricePair::new(dec!(10.5), dec!(200)),
+                PricePair::new(dec!(10), dec!(300)),
+            ]
+        );
+        assert_eq!(
+            o.asks,
+            vec![
+                PricePair::new(dec!(11.5), dec!(200)),
+                PricePair::new(dec!(12), dec!(100)),
+            ]
+        );
+
+        assert!(BookView::default().validate().is_ok());
+        assert!(BookView {
+            grouping: Some(dec!(0)),
+            ..BookView::default()
+        }
+        .validate()
+        .is_err());
+    }
+
     #[test]
     fn should_be_created_empty() {
         let o = OrderBook::new();
@@ -1,5 +1,5 @@
 use crate::model::compare::Compare;
-use crate::model::{Order, Side, Trade};
+use crate::model::{L3Order, Order, PriceBand, PricePair, Side, Trade};
 use log::debug;
 use rust_decimal::Decimal;
 use std::collections::{BTreeMap, HashSet, VecDeque};
@@ -46,7 +46,86 @@ impl OrderBookSide {
             .and_then(|entry| entry.1.iter().next())
     }
 
-    pub fn fill(&mut self, order: &mut Order) -> Vec<Trade> {
+    /// Aggregates the unfilled quantity of each price level, best price first
+    pub fn depth(&self) -> Vec<PricePair> {
+        self.levels
+            .iter()
+            .map(|(price, orders)| {
+                let quantity = orders.iter().map(|order| order.unfilled()).sum();
+                PricePair::new(**price, quantity)
+            })
+            .collect()
+    }
+
+    pub fn order_count(&self) -> usize {
+        self.levels.values().map(|lvl| lvl.len()).sum()
+    }
+
+    /// Returns all orders in priority order
+    pub fn orders(&self) -> Vec<Order> {
+        self.levels.values().flatten().cloned().collect()
+    }
+
+    /// Returns all orders in priority order with their position at their price level
+    pub fn queue(&self) -> Vec<L3Order> {
+        self.levels
+            .values()
+            .flat_map(|orders| {
+                orders
+                    .iter()
+                    .enumerate()
+                    .map(|(position, order)| L3Order::new(order, position))
+            })
+            .collect()
+    }
+
+    /// Returns the number of orders at a price level
+    pub fn level_len(&self, price: Decimal) -> usize {
+        self.levels
+            .get(&Compare::new(price, self.reverse))
+            .map_or(0, |orders| orders.len())
+    }
+
+    /// Removes the order with the highest priority
+    pub fn pop_best(&mut self) -> Option<Order> {
+        let price = *self.levels.keys().next()?;
+        let orders = self.levels.get_mut(&price)?;
+        let order = orders.pop_front();
+        if orders.is_empty() {
+            self.levels.remove(&price);
+        }
+        order
+    }
+
+    /// Puts an order back in front of its price level
+    pub fn push_best(&mut self, order: Order) {
+        self.levels
+            .entry(Compare::new(order.price, self.reverse))
+            .or_default()
+            .push_front(order);
+    }
+
+    /// Removes all orders matching the predicate
+    pub fn remove_where<P>(&mut self, mut predicate: P) -> Vec<Order>
+    where
+        P: FnMut(&Order) -> bool,
+    {
+        let mut removed = Vec::new();
+        for orders in self.levels.values_mut() {
+            let (matching, remaining): (VecDeque<_>, VecDeque<_>) =
+                orders.drain(..).partition(|order| predicate(order));
+            removed.extend(matching);
+            *orders = remaining;
+        }
+        self.levels.retain(|_, orders| !orders.is_empty());
+        removed
+    }
+
+    pub fn best_price(&self) -> Option<Decimal> {
+        self.levels.keys().next().map(|price| **price)
+    }
+
+    pub fn fill(&mut self, order: &mut Order, band: Option<PriceBand>) -> Vec<Trade> {
         let mut trades = Vec::new();
         let mut levels_to_delete = HashSet::new();
 
@@ -54,6 +133,9 @@ impl OrderBookSide {
             if order.is_filled() || !order.crosses(*opposite_order_price) {
                 break;
             }
+            if band.map_or(false, |band| !band.contains(*opposite_order_price)) {
+                break;
+            }
 
             while !order.is_filled() {
                 let Some(mut opposite_order) = opposite_orders.pop_front() else {
@@ -81,16 +163,18 @@ impl OrderBookSide {
     }
 
     fn execute_trade(order: &mut Order, other: &mut Order) -> Trade {
-        let (buy_order_id, sell_order_id) = match order.side {
-            Side::Buy => (order.id, other.id),
-            Side::Sell => (other.id, order.id),
-        };
-
         let used_qty = other.fill(order.unfilled());
         order.fill(used_qty);
         debug!("Filled bid at {}", other.price);
 
-        Trade::new(other.price, used_qty, buy_order_id, sell_order_id)
+        let trade = match order.side {
+            Side::Buy => Trade::new(other.price, used_qty, order, other),
+            Side::Sell => Trade::new(other.price, used_qty, other, order),
+        };
+        // The time of the taker is stored, so the trade is made at the same time on replay
+        trade
+            .with_taker_side(order.side)
+            .with_executed_at(order.created_at)
     }
 
     pub fn push(&mut self, order: Order) {
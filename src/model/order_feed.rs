@@ -0,0 +1,116 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::model::{ExecutionReport, Order, OrderId, OrderStatus, Side};
+
+/// A resting order without its owner, so it can be published
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct L3Order {
+    pub order_id: OrderId,
+    pub side: Side,
+    pub price: Decimal,
+    /// The unfilled quantity of the order
+    pub quantity: Decimal,
+    /// The number of orders ahead of it at its price level
+    pub position: usize,
+}
+
+impl L3Order {
+    pub fn new(order: &Order, position: usize) -> Self {
+        Self {
+            order_id: order.id,
+            side: order.side,
+            price: order.price,
+            quantity: order.unfilled(),
+            position,
+        }
+    }
+}
+
+/// A change of the resting orders, as in ITCH-style order-by-order feeds
+///
+/// Orders are added at the end of their price level and keep their position
+/// when modified, the orders behind a deleted one move up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum L3Change {
+    Add(L3Order),
+    /// The unfilled quantity of an order decreased by a fill
+    Modify {
+        order_id: OrderId,
+        quantity: Decimal,
+    },
+    /// An order was filled, cancelled or expired
+    Delete {
+        order_id: OrderId,
+    },
+}
+
+impl L3Change {
+    /// Returns the change of a resting order by its fill
+    pub fn of_fill(report: &ExecutionReport) -> Self {
+        match report.status {
+            OrderStatus::Filled => L3Change::Delete {
+                order_id: report.order_id,
+            },
+            _ => L3Change::Modify {
+                order_id: report.order_id,
+                quantity: report.quantity - report.filled,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct L3Event {
+    /// Increases by one with every change, starting over when the engine restarts
+    pub sequence: u64,
+    pub timestamp: u128,
+    pub change: L3Change,
+}
+
+/// Reads all resting orders in priority order
+#[derive(Debug)]
+pub struct ReadL3;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct L3Snapshot {
+    /// The sequence of the last change which is included
+    pub sequence: u64,
+    pub bids: Vec<L3Order>,
+    pub asks: Vec<L3Order>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{ExecutionReports, Trade};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn should_modify_and_delete_filled_orders() {
+        let mut reports = ExecutionReports::default();
+        let mut ask = Order::open_limit(OrderId(1), Side::Sell, dec!(10), dec!(100));
+        reports.accept(&ask, 0);
+
+        let mut bid = Order::open_limit(OrderId(2), Side::Buy, dec!(10), dec!(40));
+        let trade = Trade::new(dec!(10), ask.fill(bid.fill(dec!(40))), &bid, &ask);
+        let fills = reports.fill(&trade);
+        assert_eq!(
+            L3Change::of_fill(&fills[0]),
+            L3Change::Modify {
+                order_id: OrderId(1),
+                quantity: dec!(60)
+            }
+        );
+
+        let mut bid = Order::open_limit(OrderId(3), Side::Buy, dec!(10), dec!(60));
+        let trade = Trade::new(dec!(10), ask.fill(bid.fill(dec!(60))), &bid, &ask);
+        let fills = reports.fill(&trade);
+        assert_eq!(
+            L3Change::of_fill(&fills[0]),
+            L3Change::Delete {
+                order_id: OrderId(1)
+            }
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use serde::Deserialize;
+
+use crate::model::MarketState;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+const NANOS_PER_DAY: u128 = 24 * 60 * 60 * NANOS_PER_SEC;
+
+/// A daily schedule of market states in UTC
+///
+/// It is parsed from a comma separated list of times and the state the
+/// market moves into at that time, e.g. `08:00=PreOpen,09:00=Continuous`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Schedule {
+    /// Nanoseconds since midnight and the state starting then, sorted by time
+    entries: Vec<(u128, MarketState)>,
+}
+
+impl Schedule {
+    /// Returns the state the market should be in at the given time
+    pub fn state_at(&self, now: u128) -> MarketState {
+        let time_of_day = now % NANOS_PER_DAY;
+        self.entries
+            .iter()
+            .rev()
+            .find(|(time, _)| *time <= time_of_day)
+            .or_else(|| self.entries.last())
+            .map(|&(_, state)| state)
+            .unwrap_or_default()
+    }
+
+    /// Returns the time of the next state change after the given time
+    pub fn next_change(&self, now: u128) -> Option<u128> {
+        let time_of_day = now % NANOS_PER_DAY;
+        let midnight = now - time_of_day;
+        self.entries
+            .iter()
+            .find(|(time, _)| *time > time_of_day)
+            .map(|(time, _)| midnight + time)
+            .or_else(|| {
+                let (time, _) = self.entries.first()?;
+                Some(midnight + NANOS_PER_DAY + time)
+            })
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let mut entries = value
+            .split(',')
+            .map(|entry| {
+                let (time, state) = entry
+                    .trim()
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected time=state, got {entry}"))?;
+                let (hours, minutes) = time
+                    .split_once(':')
+                    .ok_or_else(|| format!("expected HH:MM, got {time}"))?;
+                let hours = hours.parse::<u128>().map_err(|e| e.to_string())?;
+                let minutes = minutes.parse::<u128>().map_err(|e| e.to_string())?;
+                if hours >= 24 || minutes >= 60 {
+                    return Err(format!("invalid time {time}"));
+                }
+
+                let time = (hours * 60 + minutes) * 60 * NANOS_PER_SEC;
+                Ok((time, state.parse()?))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        entries.sort_by_key(|&(time, _)| time);
+
+        Ok(Self { entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u128 = 60 * 60 * NANOS_PER_SEC;
+
+    fn schedule() -> Schedule {
+        Schedule::try_from("17:30=Closed, 08:00=Auction,08:05=Continuous".to_string()).unwrap()
+    }
+
+    #[test]
+    fn should_reject_an_invalid_schedule() {
+        assert!(Schedule::try_from("8=Auction".to_string()).is_err());
+        assert!(Schedule::try_from("08:00".to_string()).is_err());
+        assert!(Schedule::try_from("25:00=Auction".to_string()).is_err());
+        assert!(Schedule::try_from("08:00=Lunch".to_string()).is_err());
+    }
+
+    #[test]
+    fn should_find_the_current_state() {
+        let schedule = schedule();
+        let day = 42 * NANOS_PER_DAY;
+
+        assert_eq!(schedule.state_at(day + 7 * HOUR), MarketState::Closed);
+        assert_eq!(schedule.state_at(day + 8 * HOUR), MarketState::Auction);
+        assert_eq!(schedule.state_at(day + 12 * HOUR), MarketState::Continuous);
+        assert_eq!(schedule.state_at(day + 18 * HOUR), MarketState::Closed);
+    }
+
+    #[test]
+    fn should_find_the_next_change() {
+        let schedule = schedule();
+        let day = 42 * NANOS_PER_DAY;
+
+        assert_eq!(schedule.next_change(day + 7 * HOUR), Some(day + 8 * HOUR));
+        assert_eq!(
+            schedule.next_change(day + 8 * HOUR),
+            Some(day + 8 * HOUR + 5 * 60 * NANOS_PER_SEC)
+        );
+        assert_eq!(
+            schedule.next_change(day + 18 * HOUR),
+            Some(day + NANOS_PER_DAY + 8 * HOUR)
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::model::{
+    Account, ApiKey, Balance, Candle, MarketState, Order, PriceHistory, Ticker, Trade,
+    TradeAmendment,
+};
+
+/// The state of the engine after a number of write-ahead log entries
+///
+/// On start, the engine restores the latest snapshot and only replays the
+/// log entries written after it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub wal_entries: usize,
+    pub last_order_id: u64,
+    /// The engine sequence of the latest event
+    #[serde(default)]
+    pub engine_sequence: u64,
+    pub market_state: MarketState,
+    pub market_state_until: Option<u128>,
+    pub last: Option<Decimal>,
+    pub orders: Vec<Order>,
+    /// The latest trades of the trade history
+    pub trades: Vec<Trade>,
+    /// The number of trades ever made, which are all in `trades` if missing
+    #[serde(default)]
+    pub trade_count: Option<usize>,
+    /// All busts and corrections of trades
+    #[serde(default)]
+    pub amendments: Vec<TradeAmendment>,
+    pub balances: HashMap<String, Balance>,
+    pub price_history: PriceHistory,
+    #[serde(default)]
+    pub api_keys: HashMap<String, ApiKey>,
+    #[serde(default)]
+    pub accounts: HashMap<String, Account>,
+    /// Orders with a client order ID, including those which no longer rest
+    #[serde(default)]
+    pub client_orders: Vec<Order>,
+    /// The last execution report sequence number of each user
+    #[serde(default)]
+    pub report_sequences: HashMap<String, u64>,
+    /// The candles of all intervals, which are rebuilt from the trades if missing
+    #[serde(default)]
+    pub candles: Option<Vec<Candle>>,
+    /// The trades of the last 24 hours, which are rebuilt from the trades if missing
+    #[serde(default)]
+    pub ticker: Option<Ticker>,
+}
@@ -1,21 +1,132 @@
-use crate::model::{OrderBook, Trade};
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::model::{
+    Account, ApiKey, Balance, Candles, ClientOrders, OrderBook, TapeTrade, Ticker, Trade,
+    TradeAmendment, TradeHistory, TradeId,
+};
 
 #[derive(Clone, Debug)]
 pub struct State {
     pub order_book: OrderBook,
-    pub trades: Vec<Trade>,
+    pub trades: TradeHistory,
+    pub balances: HashMap<String, Balance>,
+    /// API keys by their public identifier
+    pub api_keys: HashMap<String, ApiKey>,
+    /// Registered users by their ID
+    pub accounts: HashMap<String, Account>,
+    pub client_orders: ClientOrders,
+    pub candles: Candles,
+    pub ticker: Ticker,
+    /// All busts and corrections in the order they were made
+    pub amendments: Vec<TradeAmendment>,
+    /// The positions of the latest amendment of each trade in the amendments
+    latest_amendments: HashMap<TradeId, usize>,
 }
 
 impl State {
-    pub fn new() -> Self {
+    pub fn new(config: &Config) -> Self {
         Self {
             order_book: OrderBook::new(),
-            trades: Vec::new(),
+            trades: TradeHistory::new(config.trade_history),
+            balances: HashMap::new(),
+            api_keys: HashMap::new(),
+            accounts: HashMap::new(),
+            client_orders: ClientOrders::default(),
+            candles: Candles::new(config.candle_history),
+            ticker: Ticker::default(),
+            amendments: Vec::new(),
+            latest_amendments: HashMap::new(),
         }
     }
 
-    pub fn push_trade(&mut self, trade: Trade) {
+    /// Publishes a trade and settles it, unless an order has no owner as in old logs
+    ///
+    /// Returns the trade as it is published on the tape.
+    pub fn push_trade(&mut self, trade: Trade) -> TapeTrade {
         self.order_book.last(trade.price);
-        self.trades.push(trade);
+        if !trade.buy_user_id.is_empty() {
+            self.balance_mut(&trade.buy_user_id).buy(&trade);
+        }
+        if !trade.sell_user_id.is_empty() {
+            self.balance_mut(&trade.sell_user_id).sell(&trade);
+        }
+        self.client_orders.fill(&trade);
+        self.candles.push(&trade);
+        self.ticker.push(&trade);
+        self.trades.push(trade.clone());
+        TapeTrade::new(trade)
+    }
+
+    /// Returns the ID of the next trade, which is its sequence in the trade history
+    pub fn next_trade_id(&self) -> TradeId {
+        TradeId(self.trades.count() as u64 + 1)
+    }
+
+    /// Busts or corrects a trade, which is given as amended before and with its owners
+    ///
+    /// The settlement of the trade is reversed and the correction settled instead,
+    /// the trade history keeps the original trade. The candles are rebuilt from
+    /// the trades made on the day of the trade, as they were made.
+    pub fn amend_trade(&mut self, trade: &Trade, amendment: TradeAmendment, day: Vec<TapeTrade>) {
+        let corrected = amendment.amendment.apply(trade.clone());
+        if !trade.buy_user_id.is_empty() {
+            let balance = self.balance_mut(&trade.buy_user_id);
+            balance.reverse_buy(trade);
+            if let Some(corrected) = &corrected {
+                balance.buy(corrected);
+            }
+        }
+        if !trade.sell_user_id.is_empty() {
+            let balance = self.balance_mut(&trade.sell_user_id);
+            balance.reverse_sell(trade);
+            if let Some(corrected) = &corrected {
+                balance.sell(corrected);
+            }
+        }
+        self.latest_amendments
+            .insert(amendment.trade_id, self.amendments.len());
+        self.amendments.push(amendment);
+
+        self.ticker.amend(trade, corrected.as_ref());
+        let trades = day
+            .into_iter()
+            .filter_map(|trade| match self.amendment(trade.trade.id) {
+                Some(amendment) => amendment.amendment.apply(trade.trade),
+                None => Some(trade.trade),
+            })
+            .collect::<Vec<_>>();
+        self.candles.amend(trade, &trades);
+    }
+
+    /// Returns the latest amendment of a trade
+    pub fn amendment(&self, trade_id: TradeId) -> Option<&TradeAmendment> {
+        let position = *self.latest_amendments.get(&trade_id)?;
+        self.amendments.get(position)
+    }
+
+    /// Replaces the amendments and indexes the latest one of each trade
+    pub fn restore_amendments(&mut self, amendments: Vec<TradeAmendment>) {
+        self.latest_amendments = amendments
+            .iter()
+            .enumerate()
+            .map(|(position, amendment)| (amendment.trade_id, position))
+            .collect();
+        self.amendments = amendments;
+    }
+
+    /// Annotates trades of the trade history with their latest amendments
+    pub fn with_amendments(&self, mut trades: Vec<TapeTrade>) -> Vec<TapeTrade> {
+        if self.amendments.is_empty() {
+            return trades;
+        }
+        for trade in &mut trades {
+            trade.amendment = self.amendment(trade.trade.id).cloned();
+        }
+        trades
+    }
+
+    pub fn balance_mut(&mut self, user_id: &str) -> &mut Balance {
+        self.balances.entry(user_id.to_string()).or_default()
     }
 }
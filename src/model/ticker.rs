@@ -0,0 +1,290 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::model::{OrderBook, PricePair, Trade, TradeId};
+
+/// The duration of the rolling window in nanoseconds
+const WINDOW: u128 = 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Tick {
+    /// The number of ticks pushed before this one
+    index: u64,
+    #[serde(default)]
+    trade_id: TradeId,
+    time: u128,
+    price: Decimal,
+    quantity: Decimal,
+}
+
+/// The trades of the last 24 hours with running sums and extremes
+///
+/// The high and low are tracked with monotonic queues, so a trade neither
+/// rescans the window when it arrives nor when it leaves.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ticker {
+    ticks: VecDeque<Tick>,
+    pushed: u64,
+    /// Candidates for the high in the order of their trades, with decreasing prices
+    highs: VecDeque<Tick>,
+    /// Candidates for the low in the order of their trades, with increasing prices
+    lows: VecDeque<Tick>,
+    volume: Decimal,
+    quote_volume: Decimal,
+}
+
+/// The statistics of the trades in the last 24 hours and the best prices
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TickerStats {
+    pub open: Option<Decimal>,
+    pub high: Option<Decimal>,
+    pub low: Option<Decimal>,
+    pub last: Option<Decimal>,
+    pub volume: Decimal,
+    pub quote_volume: Decimal,
+    /// The volume weighted average price
+    pub vwap: Option<Decimal>,
+    pub trades: u64,
+    /// The difference between the last and the open price
+    pub price_change: Option<Decimal>,
+    pub price_change_percent: Option<Decimal>,
+    pub best_bid: Option<PricePair>,
+    pub best_ask: Option<PricePair>,
+}
+
+impl Ticker {
+    pub fn push(&mut self, trade: &Trade) {
+        let tick = Tick {
+            index: self.pushed,
+            trade_id: trade.id,
+            time: trade.executed_at,
+            price: trade.price,
+            quantity: trade.quantity,
+        };
+        self.pushed += 1;
+        self.evict(tick.time.saturating_sub(WINDOW));
+
+        self.track(tick);
+        self.volume += tick.quantity;
+        self.quote_volume += tick.price * tick.quantity;
+        self.ticks.push_back(tick);
+    }
+
+    /// Removes a busted trade from the window or replaces it by its correction
+    ///
+    /// The candidates for the high and low are tracked again from all ticks,
+    /// which only an amendment requires.
+    pub fn amend(&mut self, trade: &Trade, corrected: Option<&Trade>) {
+        let Some(position) = self.ticks.iter().position(|tick| tick.trade_id == trade.id) else {
+            return;
+        };
+        let tick = self.ticks[position];
+        self.volume -= tick.quantity;
+        self.quote_volume -= tick.price * tick.quantity;
+        match corrected {
+            Some(corrected) => {
+                let tick = &mut self.ticks[position];
+                tick.price = corrected.price;
+                tick.quantity = corrected.quantity;
+                self.volume += tick.quantity;
+                self.quote_volume += tick.price * tick.quantity;
+            }
+            None => {
+                self.ticks.remove(position);
+            }
+        }
+
+        self.highs.clear();
+        self.lows.clear();
+        for tick in self.ticks.clone() {
+            self.track(tick);
+        }
+    }
+
+    /// Adds a tick to the candidates for the high and low
+    fn track(&mut self, tick: Tick) {
+        while self
+            .highs
+            .back()
+            .map_or(false, |high| high.price <= tick.price)
+        {
+            self.highs.pop_back();
+        }
+        self.highs.push_back(tick);
+        while self
+            .lows
+            .back()
+            .map_or(false, |low| low.price >= tick.price)
+        {
+            self.lows.pop_back();
+        }
+        self.lows.push_back(tick);
+    }
+
+    /// Removes the ticks before the given time
+    fn evict(&mut self, since: u128) {
+        while let Some(tick) = self.ticks.front().filter(|tick| tick.time < since).copied() {
+            self.ticks.pop_front();
+            self.volume -= tick.quantity;
+            self.quote_volume -= tick.price * tick.quantity;
+            if self.highs.front().map(|high| high.index) == Some(tick.index) {
+                self.highs.pop_front();
+            }
+            if self.lows.front().map(|low| low.index) == Some(tick.index) {
+                self.lows.pop_front();
+            }
+        }
+    }
+
+    /// Returns the statistics of the 24 hours before now
+    ///
+    /// Ticks which expired since the last trade are skipped without removing them.
+    pub fn stats(&self, now: u128, order_book: &OrderBook) -> TickerStats {
+        let since = now.saturating_sub(WINDOW);
+        let expired = self.ticks.iter().take_while(|tick| tick.time < since);
+        let mut volume = self.volume;
+        let mut quote_volume = self.quote_volume;
+        let mut count = 0;
+        for tick in expired {
+            volume -= tick.quantity;
+            quote_volume -= tick.price * tick.quantity;
+            count += 1;
+        }
+
+        let open = self.ticks.get(count);
+        let last = open.and(self.ticks.back());
+        let first = open.map_or(self.pushed, |tick| tick.index);
+        let high = self.highs.iter().find(|tick| tick.index >= first);
+        let low = self.lows.iter().find(|tick| tick.index >= first);
+        let price_change = open.zip(last).map(|(open, last)| last.price - open.price);
+
+        TickerStats {
+            open: open.map(|tick| tick.price),
+            high: high.map(|tick| tick.price),
+            low: low.map(|tick| tick.price),
+            last: last.map(|tick| tick.price),
+            volume,
+            quote_volume,
+            vwap: (volume > Decimal::ZERO).then(|| quote_volume / volume),
+            trades: (self.ticks.len() - count) as u64,
+            price_change,
+            price_change_percent: price_change
+                .zip(open)
+                .map(|(change, open)| (change / open.price * Decimal::ONE_HUNDRED).round_dp(2)),
+            best_bid: order_book.bids.first().cloned(),
+            best_ask: order_book.asks.first().cloned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Order, OrderId, Side};
+    use rust_decimal_macros::dec;
+
+    const HOUR: u128 = 60 * 60 * 1_000_000_000;
+
+    fn trade(price: Decimal, quantity: Decimal, time: u128) -> Trade {
+        let bid = Order::open_limit(OrderId(1), Side::Buy, price, quantity);
+        let ask = Order::open_limit(OrderId(2), Side::Sell, price, quantity);
+        let mut trade = Trade::new(price, quantity, &bid, &ask);
+        trade.executed_at = time;
+        trade
+    }
+
+    #[test]
+    fn should_compute_the_statistics_of_the_window() {
+        let mut ticker = Ticker::default();
+        ticker.push(&trade(dec!(10), dec!(1), HOUR));
+        ticker.push(&trade(dec!(14), dec!(1), 2 * HOUR));
+        ticker.push(&trade(dec!(8), dec!(2), 3 * HOUR));
+        ticker.push(&trade(dec!(12), dec!(1), 4 * HOUR));
+
+        let mut order_book = OrderBook::new();
+        order_book.replace(
+            vec![PricePair::new(dec!(11), dec!(3))],
+            vec![PricePair::new(dec!(13), dec!(5))],
+        );
+        let stats = ticker.stats(5 * HOUR, &order_book);
+        assert_eq!(
+            TickerStats {
+                open: Some(dec!(10)),
+                high: Some(dec!(14)),
+                low: Some(dec!(8)),
+                last: Some(dec!(12)),
+                volume: dec!(5),
+                quote_volume: dec!(52),
+                vwap: Some(dec!(10.4)),
+                trades: 4,
+                price_change: Some(dec!(2)),
+                price_change_percent: Some(dec!(20)),
+                best_bid: Some(PricePair::new(dec!(11), dec!(3))),
+                best_ask: Some(PricePair::new(dec!(13), dec!(5))),
+            },
+            stats
+        );
+    }
+
+    #[test]
+    fn should_slide_the_window() {
+        let mut ticker = Ticker::default();
+        ticker.push(&trade(dec!(14), dec!(1), HOUR));
+        ticker.push(&trade(dec!(8), dec!(1), 2 * HOUR));
+        ticker.push(&trade(dec!(10), dec!(1), 3 * HOUR));
+
+        let stats = ticker.stats(25 * HOUR + 1, &OrderBook::new());
+        assert_eq!(
+            (stats.open, stats.high, stats.low, stats.trades),
+            (Some(dec!(8)), Some(dec!(10)), Some(dec!(8)), 2)
+        );
+
+        ticker.push(&trade(dec!(9), dec!(1), 26 * HOUR + 1));
+        assert_eq!(ticker.ticks.len(), 2);
+        let stats = ticker.stats(26 * HOUR + 1, &OrderBook::new());
+        assert_eq!(
+            (stats.high, stats.low, stats.quote_volume),
+            (Some(dec!(10)), Some(dec!(9)), dec!(19))
+        );
+
+        let stats = ticker.stats(100 * HOUR, &OrderBook::new());
+        assert_eq!(
+            (stats.open, stats.high, stats.last, stats.trades),
+            (None, None, None, 0)
+        );
+        assert_eq!((stats.volume, stats.vwap), (dec!(0), None));
+    }
+
+    #[test]
+    fn should_amend_a_trade_of_the_window() {
+        let mut ticker = Ticker::default();
+        let trades = [
+            trade(dec!(10), dec!(1), HOUR).with_id(TradeId(1)),
+            trade(dec!(14), dec!(1), 2 * HOUR).with_id(TradeId(2)),
+            trade(dec!(12), dec!(1), 3 * HOUR).with_id(TradeId(3)),
+        ];
+        for trade in &trades {
+            ticker.push(trade);
+        }
+
+        let corrected = Trade {
+            price: dec!(8),
+            quantity: dec!(2),
+            ..trades[1].clone()
+        };
+        ticker.amend(&trades[1], Some(&corrected));
+        let stats = ticker.stats(4 * HOUR, &OrderBook::new());
+        assert_eq!(
+            (stats.high, stats.low, stats.volume, stats.quote_volume),
+            (Some(dec!(12)), Some(dec!(8)), dec!(4), dec!(38))
+        );
+
+        ticker.amend(&trades[0], None);
+        let stats = ticker.stats(4 * HOUR, &OrderBook::new());
+        assert_eq!(
+            (stats.open, stats.last, stats.volume, stats.trades),
+            (Some(dec!(8)), Some(dec!(12)), dec!(3), 2)
+        );
+    }
+}
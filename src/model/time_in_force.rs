@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimeInForce {
+    #[default]
+    GoodTillCancelled,
+    /// Expires when the market closes
+    Day,
+}
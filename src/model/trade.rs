@@ -2,33 +2,70 @@ use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::model::OrderId;
+use crate::model::{Order, OrderId, Side};
+
+/// Increases by one with every trade, so it is also the position of the trade in the trade history
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TradeId(pub u64);
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Trade {
+    #[serde(default)]
+    pub id: TradeId,
+    /// The engine sequence of the trade, see [`Order::engine_sequence`]
+    #[serde(default)]
+    pub engine_sequence: u64,
     pub price: Decimal,
     pub quantity: Decimal,
     pub buy_order_id: OrderId,
     pub sell_order_id: OrderId,
     pub executed_at: u128,
+    /// The side of the order which took liquidity, auction trades have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taker_side: Option<Side>,
+    /// Owners of the orders, which are settled but never published
+    #[serde(skip)]
+    pub buy_user_id: String,
+    #[serde(skip)]
+    pub sell_user_id: String,
 }
 
 impl Trade {
-    pub fn new(
-        price: Decimal,
-        quantity: Decimal,
-        buy_order_id: OrderId,
-        sell_order_id: OrderId,
-    ) -> Self {
+    pub fn new(price: Decimal, quantity: Decimal, buy_order: &Order, sell_order: &Order) -> Self {
         let now = SystemTime::now();
         let executed_at = now.duration_since(UNIX_EPOCH).unwrap().as_nanos();
 
         Self {
+            id: TradeId::default(),
+            engine_sequence: 0,
             price,
             quantity,
-            buy_order_id,
-            sell_order_id,
+            buy_order_id: buy_order.id,
+            sell_order_id: sell_order.id,
             executed_at,
+            taker_side: None,
+            buy_user_id: buy_order.user_id.clone(),
+            sell_user_id: sell_order.user_id.clone(),
         }
     }
+
+    pub fn with_id(mut self, id: TradeId) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_engine_sequence(mut self, engine_sequence: u64) -> Self {
+        self.engine_sequence = engine_sequence;
+        self
+    }
+
+    pub fn with_executed_at(mut self, executed_at: u128) -> Self {
+        self.executed_at = executed_at;
+        self
+    }
+
+    pub fn with_taker_side(mut self, taker_side: Side) -> Self {
+        self.taker_side = Some(taker_side);
+        self
+    }
 }
@@ -0,0 +1,66 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::model::{Trade, TradeId};
+
+/// How a trade is amended
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Amendment {
+    /// Reverses the trade as if it was never made
+    Bust,
+    /// Replaces the price and quantity of the trade
+    Correct { price: Decimal, quantity: Decimal },
+}
+
+impl Amendment {
+    /// Returns the trade as amended, a busted trade has none
+    pub fn apply(&self, trade: Trade) -> Option<Trade> {
+        match *self {
+            Amendment::Bust => None,
+            Amendment::Correct { price, quantity } => Some(Trade {
+                price,
+                quantity,
+                ..trade
+            }),
+        }
+    }
+}
+
+/// Busts or corrects a trade by compensating its effects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmendTrade {
+    pub trade_id: TradeId,
+    pub amendment: Amendment,
+}
+
+impl AmendTrade {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match self.amendment {
+            Amendment::Correct { price, quantity }
+                if price <= Decimal::ZERO || quantity <= Decimal::ZERO =>
+            {
+                Err("price and quantity must be positive")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A bust or correction of a trade, which is published on the trade stream
+///
+/// The trade history keeps the original trade, later amendments of the same
+/// trade apply to the trade as corrected by the earlier ones.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeAmendment {
+    pub trade_id: TradeId,
+    pub engine_sequence: u64,
+    pub amendment: Amendment,
+    pub amended_at: u128,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AmendTradeError {
+    UnknownTrade,
+    /// The trade was busted before, so there is nothing left to amend
+    Busted,
+}
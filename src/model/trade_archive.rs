@@ -0,0 +1,266 @@
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::{TapeTrade, Trade, TradeId};
+
+const ARCHIVE_FILE: &str = "trades.log";
+
+/// An archived trade with its owners, which are needed to amend it
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveEntry {
+    sequence: usize,
+    trade: Trade,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    buy_user_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    sell_user_id: String,
+}
+
+impl ArchiveEntry {
+    /// Trades archived before trade IDs existed take theirs from the sequence
+    fn into_trade(self) -> TapeTrade {
+        TapeTrade::new(Trade {
+            id: TradeId(self.sequence as u64),
+            buy_user_id: self.buy_user_id,
+            sell_user_id: self.sell_user_id,
+            ..self.trade
+        })
+    }
+
+    fn offset(&self, offset: u64) -> ArchiveOffset {
+        ArchiveOffset {
+            sequence: self.sequence,
+            executed_at: self.trade.executed_at,
+            offset,
+        }
+    }
+}
+
+/// The position of an archived trade in the file
+#[derive(Debug, Clone, Copy)]
+struct ArchiveOffset {
+    sequence: usize,
+    executed_at: u128,
+    offset: u64,
+}
+
+/// All trades ever made, next to the write-ahead log
+///
+/// The trades are derived from the log, so trades which are made again
+/// while the log is replayed are only appended if they are missing.
+#[derive(Debug)]
+pub struct TradeArchive {
+    file: BufWriter<File>,
+    reader: ArchiveReader,
+    /// The sequence of the latest archived trade
+    archived: usize,
+    /// The length of the file, which is the offset of the next trade
+    len: u64,
+}
+
+/// Reads the trade archive with the index its writer keeps up to date
+#[derive(Debug, Clone)]
+pub struct ArchiveReader {
+    path: PathBuf,
+    /// The offsets of the archived trades in ascending order of their sequences
+    offsets: Arc<RwLock<Vec<ArchiveOffset>>>,
+}
+
+impl TradeArchive {
+    pub fn new(path_dir: &Path) -> Result<Self> {
+        create_dir_all(path_dir)?;
+        let path = path_dir.join(ARCHIVE_FILE);
+        let file = OpenOptions::new().append(true).create(true).open(&path)?;
+
+        let mut offsets = Vec::new();
+        let mut len = 0;
+        let mut reader = BufReader::new(File::open(&path)?);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                break;
+            }
+            match serde_json::from_str::<ArchiveEntry>(&line) {
+                Ok(entry) => offsets.push(entry.offset(len)),
+                Err(err) => warn!("Failed to read archived trade: {}", err),
+            }
+            len += read as u64;
+        }
+        let archived = offsets.last().map_or(0, |offset| offset.sequence);
+
+        Ok(TradeArchive {
+            file: BufWriter::new(file),
+            reader: ArchiveReader {
+                path,
+                offsets: Arc::new(RwLock::new(offsets)),
+            },
+            archived,
+            len,
+        })
+    }
+
+    /// Returns a reader which shares the index of the archive
+    pub fn reader(&self) -> ArchiveReader {
+        self.reader.clone()
+    }
+
+    pub fn append(&mut self, trade: &TapeTrade) -> Result<()> {
+        let sequence = trade.sequence();
+        if sequence <= self.archived {
+            return Ok(());
+        }
+
+        let entry = ArchiveEntry {
+            sequence,
+            trade: trade.trade.clone(),
+            buy_user_id: trade.trade.buy_user_id.clone(),
+            sell_user_id: trade.trade.sell_user_id.clone(),
+        };
+        let line = format!("{}\n", serde_json::to_string(&entry)?);
+        self.file.write_all(line.as_bytes())?;
+        self.file.flush()?;
+        self.archived = sequence;
+        let mut offsets = self.reader.offsets.write().unwrap();
+        offsets.push(entry.offset(self.len));
+        self.len += line.len() as u64;
+
+        Ok(())
+    }
+
+    /// Reads an archived trade with its owners
+    pub fn get(&self, sequence: usize) -> Result<Option<Trade>> {
+        self.reader.get(sequence)
+    }
+}
+
+impl ArchiveReader {
+    /// Reads the archived trades whose sequences are in the range
+    pub fn read(&self, range: RangeInclusive<usize>) -> Result<Vec<TapeTrade>> {
+        let (start, count) = {
+            let offsets = self.offsets.read().unwrap();
+            let first = offsets.partition_point(|offset| offset.sequence < *range.start());
+            let last = offsets.partition_point(|offset| offset.sequence <= *range.end());
+            match offsets.get(first) {
+                Some(offset) if first < last => (offset.offset, last - first),
+                _ => return Ok(Vec::new()),
+            }
+        };
+
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut trades = Vec::with_capacity(count);
+        for line in BufReader::new(file).lines() {
+            let entry = match serde_json::from_str::<ArchiveEntry>(&line?) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!("Failed to read archived trade: {}", err);
+                    continue;
+                }
+            };
+            if entry.sequence > *range.end() {
+                break;
+            }
+            trades.push(entry.into_trade());
+            if trades.len() == count {
+                break;
+            }
+        }
+        Ok(trades)
+    }
+
+    /// Returns the number of archived trades executed before the time
+    ///
+    /// The trades are archived in the order they were executed, so the time is searched for.
+    pub fn executed_before(&self, time: u128) -> usize {
+        let offsets = self.offsets.read().unwrap();
+        let position = offsets.partition_point(|offset| offset.executed_at < time);
+        position
+            .checked_sub(1)
+            .map_or(0, |position| offsets[position].sequence)
+    }
+
+    /// Reads an archived trade with its owners at its offset in the file
+    pub fn get(&self, sequence: usize) -> Result<Option<Trade>> {
+        let offset = {
+            let offsets = self.offsets.read().unwrap();
+            let Ok(index) = offsets.binary_search_by_key(&sequence, |offset| offset.sequence)
+            else {
+                return Ok(None);
+            };
+            offsets[index].offset
+        };
+
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut line = String::new();
+        BufReader::new(file).read_line(&mut line)?;
+        let entry: ArchiveEntry = serde_json::from_str(&line)?;
+        Ok(Some(entry.into_trade().trade))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Order, OrderId, Side};
+    use rust_decimal_macros::dec;
+    use std::env::temp_dir;
+    use std::fs::remove_dir_all;
+
+    /// A directory which is removed when the test ends, also when it fails
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn should_append_missing_trades_only() {
+        let dir = TempDir(temp_dir().join(format!("trade-archive-{}", std::process::id())));
+        let bid = Order::open_limit(OrderId(1), Side::Buy, dec!(10), dec!(1));
+        let ask = Order::open_limit(OrderId(2), Side::Sell, dec!(10), dec!(1))
+            .with_user_id("alice".into());
+        let trade =
+            |id| TapeTrade::new(Trade::new(dec!(10), dec!(1), &bid, &ask).with_id(TradeId(id)));
+
+        let mut archive = TradeArchive::new(&dir.0).unwrap();
+        archive.append(&trade(1)).unwrap();
+        archive.append(&trade(2)).unwrap();
+        let mut archive = TradeArchive::new(&dir.0).unwrap();
+        archive.append(&trade(2)).unwrap();
+        archive.append(&trade(3)).unwrap();
+
+        let owner = |sequence| {
+            archive
+                .get(sequence)
+                .unwrap()
+                .map(|trade| trade.sell_user_id)
+        };
+        assert_eq!(
+            (owner(1), owner(3), owner(4)),
+            (Some("alice".into()), Some("alice".into()), None)
+        );
+        assert_eq!(archive.reader().executed_before(u128::MAX), 3);
+        assert_eq!(
+            archive
+                .reader()
+                .read(2..=5)
+                .unwrap()
+                .iter()
+                .map(|trade| trade.sequence())
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+use crate::model::{deserialize_query_nanos, Trade, TradeAmendment, TradeId};
+
+/// The number of trades returned by default
+const DEFAULT_LIMIT: usize = 100;
+pub const MAX_TRADE_PAGE: usize = 1000;
+
+/// A trade of the public tape
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TapeTrade {
+    #[serde(flatten)]
+    pub trade: Trade,
+    /// The latest bust or correction of the trade, which is kept as it was made
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amendment: Option<TradeAmendment>,
+}
+
+impl TapeTrade {
+    pub fn new(trade: Trade) -> Self {
+        Self {
+            trade,
+            amendment: None,
+        }
+    }
+
+    /// Returns the position of the trade in the trade history, which is its ID
+    pub fn sequence(&self) -> usize {
+        self.trade.id.0 as usize
+    }
+}
+
+/// An event of the trade stream, either a new trade or an amendment of an earlier one
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum TapeEvent {
+    Trade(TapeTrade),
+    Amendment(TradeAmendment),
+    /// The sequences of trades which are skipped as they are missing in the trade archive
+    Missing {
+        missing: RangeInclusive<usize>,
+    },
+}
+
+/// The latest trades in a ring buffer, older ones are only kept in the trade archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradeHistory {
+    capacity: usize,
+    trades: VecDeque<Trade>,
+    /// The number of trades ever made, which is the sequence of the latest one
+    count: usize,
+}
+
+impl TradeHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            trades: VecDeque::with_capacity(capacity),
+            count: 0,
+        }
+    }
+
+    /// Adds a trade, dropping the oldest one when full
+    pub fn push(&mut self, trade: Trade) {
+        if self.trades.len() == self.capacity {
+            self.trades.pop_front();
+        }
+        if self.capacity > 0 {
+            self.trades.push_back(trade);
+        }
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the sequence of the oldest trade in memory
+    pub fn first_sequence(&self) -> usize {
+        self.count - self.trades.len() + 1
+    }
+
+    /// Returns the trades in memory whose sequences are in the range
+    pub fn range(&self, range: RangeInclusive<usize>) -> Vec<TapeTrade> {
+        let first = self.first_sequence();
+        self.trades
+            .iter()
+            .zip(first..)
+            .skip(range.start().saturating_sub(first))
+            .take_while(|(_, sequence)| sequence <= range.end())
+            .map(|(trade, _)| TapeTrade::new(trade.clone()))
+            .collect()
+    }
+
+    /// Returns the number of trades executed before the time, unless older trades are needed
+    ///
+    /// The trades are in the order they were executed, so the time is searched for.
+    pub fn executed_before(&self, time: u128) -> Option<usize> {
+        let position = self
+            .trades
+            .partition_point(|trade| trade.executed_at < time);
+        let first = self.first_sequence();
+        (position > 0 || first == 1).then_some(first - 1 + position)
+    }
+
+    pub fn trades(&self) -> Vec<Trade> {
+        self.trades.iter().cloned().collect()
+    }
+
+    /// Replaces the trades, keeping the latest ones
+    ///
+    /// Snapshots without a count contain all trades ever made.
+    pub fn restore(&mut self, trades: Vec<Trade>, count: Option<usize>) {
+        self.count = count.unwrap_or(trades.len());
+        self.trades = trades.into_iter().collect();
+        while self.trades.len() > self.capacity {
+            self.trades.pop_front();
+        }
+        // Snapshots taken before trade IDs existed contain trades without them
+        let first = self.first_sequence();
+        for (trade, sequence) in self.trades.iter_mut().zip(first..) {
+            trade.id = TradeId(sequence as u64);
+        }
+    }
+}
+
+/// A page of the trade history, which selects the latest trades by default
+///
+/// With only `after`, it pages forward from the oldest trades after it,
+/// otherwise backward from the newest trades before `before`.
+/// The times `from` and `to` are resolved into these sequences with [`TradePage::between`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct TradePage {
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// The sequence of a trade after which the page starts
+    #[serde(default)]
+    pub after: Option<usize>,
+    /// The sequence of a trade before which the page ends
+    #[serde(default)]
+    pub before: Option<usize>,
+    /// The time in nanoseconds since the epoch the trades are executed at or after
+    #[serde(default, deserialize_with = "deserialize_query_nanos")]
+    pub from: Option<u128>,
+    /// The time in nanoseconds since the epoch the trades are executed at or before
+    #[serde(default, deserialize_with = "deserialize_query_nanos")]
+    pub to: Option<u128>,
+}
+
+impl TradePage {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        match (self.limit, self.from, self.to) {
+            (Some(limit), _, _) if limit == 0 || limit > MAX_TRADE_PAGE => {
+                Err("limit must be between 1 and 1000")
+            }
+            (_, Some(from), Some(to)) if from > to => Err("from must not be after to"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Narrows the page to the trades after and before the given sequences
+    ///
+    /// These are the number of trades executed before `from`, and the sequence
+    /// of the first trade executed after `to`.
+    pub fn between(&self, after: Option<usize>, before: Option<usize>) -> Self {
+        let before = match (self.before, before) {
+            (Some(cursor), Some(bound)) => Some(cursor.min(bound)),
+            (cursor, bound) => cursor.or(bound),
+        };
+        Self {
+            limit: self.limit,
+            after: self.after.max(after),
+            before,
+            from: None,
+            to: None,
+        }
+    }
+
+    /// Returns the sequences of the page, oldest first, given the number of trades
+    pub fn range(&self, count: usize) -> RangeInclusive<usize> {
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT);
+        let lower = self.after.map_or(1, |after| after.saturating_add(1));
+        let upper = self
+            .before
+            .map_or(count, |before| before.saturating_sub(1))
+            .min(count);
+        match (self.after, self.before) {
+            (Some(_), None) => lower..=upper.min(lower.saturating_add(limit - 1)),
+            _ => lower.max((upper + 1).saturating_sub(limit))..=upper,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Order, OrderId, Side};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn trade(price: Decimal) -> Trade {
+        let bid = Order::open_limit(OrderId(1), Side::Buy, price, dec!(1));
+        let ask = Order::open_limit(OrderId(2), Side::Sell, price, dec!(1));
+        Trade::new(price, dec!(1), &bid, &ask)
+    }
+
+    #[test]
+    fn should_keep_the_latest_trades() {
+        let mut history = TradeHistory::new(3);
+        for price in 1..=5 {
+            history.push(trade(price.into()).with_id(TradeId(price)));
+        }
+
+        assert_eq!((history.count(), history.first_sequence()), (5, 3));
+        let trades = history.range(1..=4);
+        assert_eq!(
+            trades
+                .iter()
+                .map(|trade| (trade.sequence(), trade.trade.price))
+                .collect::<Vec<_>>(),
+            vec![(3, dec!(3)), (4, dec!(4))]
+        );
+
+        let mut restored = TradeHistory::new(2);
+        restored.restore(history.trades(), Some(history.count()));
+        assert_eq!((restored.count(), restored.first_sequence()), (5, 4));
+    }
+
+    #[test]
+    fn should_select_pages() {
+        let page = |limit, after, before| TradePage {
+            limit,
+            after,
+            before,
+            ..TradePage::default()
+        };
+        assert_eq!(page(None, None, None).range(250), 151..=250);
+        assert_eq!(page(Some(10), None, None).range(5), 1..=5);
+        assert_eq!(page(Some(10), None, Some(50)).range(250), 40..=49);
+        assert_eq!(page(Some(10), Some(50), None).range(250), 51..=60);
+        assert_eq!(page(Some(10), Some(245), None).range(250), 246..=250);
+        assert_eq!(page(Some(10), Some(20), Some(25)).range(250), 21..=24);
+        assert!(page(None, Some(250), None).range(250).is_empty());
+        assert!(page(None, None, None).range(0).is_empty());
+
+        assert!(page(Some(0), None, None).validate().is_err());
+        assert!(page(Some(MAX_TRADE_PAGE + 1), None, None)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn should_select_pages_of_a_time_range() {
+        let mut history = TradeHistory::new(3);
+        for price in 1..=5 {
+            history.push(trade(price.into()).with_executed_at(price * 10));
+        }
+        assert_eq!(history.executed_before(30), None);
+        assert_eq!(history.executed_before(31), Some(3));
+        assert_eq!(history.executed_before(60), Some(5));
+
+        let page = TradePage {
+            from: Some(20),
+            to: Some(40),
+            ..TradePage::default()
+        };
+        assert!(page.validate().is_ok());
+        let page = page.between(Some(1), Some(5));
+        assert_eq!(
+            (page.after, page.before, page.from),
+            (Some(1), Some(5), None)
+        );
+        assert_eq!(page.range(5), 2..=4);
+
+        let page = TradePage {
+            after: Some(2),
+            before: Some(10),
+            ..TradePage::default()
+        };
+        assert_eq!(page.between(Some(1), Some(5)).range(5), 3..=4);
+
+        let page = TradePage {
+            from: Some(40),
+            to: Some(20),
+            ..TradePage::default()
+        };
+        assert!(page.validate().is_err());
+    }
+}
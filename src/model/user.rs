@@ -1,12 +1,80 @@
 use serde::Serialize;
 
+use crate::model::{ApiKey, Scope};
+
+pub const ADMIN_ROLE: &str = "admin";
+
 #[derive(Debug, PartialEq, Eq, Clone, Serialize)]
 pub struct User {
     user_id: String,
+    roles: Vec<String>,
+    scopes: Vec<Scope>,
+    /// The API key which signed the request, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_key: Option<String>,
 }
 
 impl User {
-    pub fn new(user_id: String) -> Self {
-        Self { user_id }
+    /// Creates a user which logged in with all scopes
+    pub fn new(user_id: String, roles: Vec<String>) -> Self {
+        Self {
+            user_id,
+            roles,
+            scopes: Scope::ALL.to_vec(),
+            api_key: None,
+        }
+    }
+
+    /// Creates a user which is limited to the scopes of an API key
+    pub fn from_api_key(key: &ApiKey) -> Self {
+        Self {
+            user_id: key.user_id.clone(),
+            roles: Vec::new(),
+            scopes: key.scopes.clone(),
+            api_key: Some(key.key.clone()),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.user_id
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.roles.iter().any(|role| role == ADMIN_ROLE)
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// Returns whether the user authenticated with an API key instead of a login
+    pub fn is_api_key(&self) -> bool {
+        self.api_key.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_limit_api_keys_to_their_scopes() {
+        let key = ApiKey {
+            key: "key".into(),
+            secret: "secret".into(),
+            user_id: "bot".into(),
+            scopes: vec![Scope::Read],
+            created_at: 0,
+        };
+        let user = User::from_api_key(&key);
+
+        assert_eq!(user.id(), "bot");
+        assert!(user.allows(Scope::Read));
+        assert!(!user.allows(Scope::Trade));
+        assert!(user.is_api_key());
+
+        let user = User::new("bot".into(), Vec::new());
+        assert!(user.allows(Scope::Trade));
+        assert!(!user.is_api_key());
     }
 }
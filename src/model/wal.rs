@@ -1,16 +1,54 @@
-use std::fs::{create_dir_all, read_dir, File, OpenOptions};
+use std::fs::{create_dir_all, read_dir, rename, File, OpenOptions};
 use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use log::warn;
+use serde::{Deserialize, Serialize};
 
-use super::Order;
+use super::{
+    Account, AdjustBalance, ApiKey, CancelOrders, MarketState, Order, RevokeApiKey, Snapshot,
+    TradeAmendment,
+};
+
+const SNAPSHOT_FILE: &str = "snapshot.json";
+
+/// An entry of the write-ahead log
+///
+/// Orders are stored as is, so logs written before other events existed can
+/// still be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalEntry {
+    Order(Order),
+    Event(WalEvent),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WalEvent {
+    /// The market moved into another state at a time, which may end at the given time
+    ///
+    /// Auction trades are stamped with the time of the transition, logs written
+    /// before it was stored use the time of the replay.
+    Transition {
+        state: MarketState,
+        until: Option<u128>,
+        #[serde(default)]
+        at: Option<u128>,
+    },
+    Cancel(CancelOrders),
+    AdjustBalance(AdjustBalance),
+    CreateApiKey(ApiKey),
+    RevokeApiKey(RevokeApiKey),
+    Register(Account),
+    /// A trade was busted or corrected, which compensates its effects on replay
+    AmendTrade(TradeAmendment),
+}
 
 #[derive(Debug)]
 pub struct WriteAheadLog {
     file: BufWriter<File>,
     path: PathBuf,
+    entries: usize,
 }
 
 impl WriteAheadLog {
@@ -26,31 +64,72 @@ impl WriteAheadLog {
         Ok(WriteAheadLog {
             file,
             path: path_dir.into(),
+            entries: 0,
         })
     }
 
     pub fn append_order(&mut self, order: &Order) -> Result<()> {
+        self.append(order)
+    }
+
+    pub fn append_event(&mut self, event: &WalEvent) -> Result<()> {
+        self.append(event)
+    }
+
+    fn append<T: Serialize>(&mut self, entry: &T) -> Result<()> {
         // Serialization
-        let entry = serde_json::to_string(order)?;
+        let entry = serde_json::to_string(entry)?;
 
         // Write on file
         writeln!(self.file, "{entry}")?;
         self.file.flush()?;
+        self.entries += 1;
 
         Ok(())
     }
 
-    pub fn read_orders(&mut self) -> Vec<Order> {
+    pub fn read_entries(&mut self) -> Vec<WalEntry> {
         let files = self.get_files_path();
         if files.is_empty() {
             return Vec::new();
         }
 
-        files
+        let entries: Vec<_> = files
             .into_iter()
             .filter_map(|file| Self::read_log_file(&file).ok())
             .flatten()
-            .collect()
+            .collect();
+        self.entries = entries.len();
+        entries
+    }
+
+    /// Returns the number of entries read and appended
+    pub fn entry_count(&self) -> usize {
+        self.entries
+    }
+
+    pub fn write_snapshot(&mut self, snapshot: &Snapshot) -> Result<()> {
+        let path = self.path.join(SNAPSHOT_FILE);
+        let tmp_path = path.with_extension("tmp");
+
+        let mut file = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(&mut file, snapshot)?;
+        file.flush()?;
+        file.get_ref().sync_all()?;
+        rename(tmp_path, path)?;
+
+        Ok(())
+    }
+
+    pub fn read_snapshot(&self) -> Result<Option<Snapshot>> {
+        let path = self.path.join(SNAPSHOT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let file = BufReader::new(File::open(path)?);
+        let snapshot = serde_json::from_reader(file)?;
+        Ok(Some(snapshot))
     }
 
     fn get_files_path(&mut self) -> Vec<PathBuf> {
@@ -62,13 +141,14 @@ impl WriteAheadLog {
                     .ok()
             })
             .map(|file| file.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "wal"))
             .collect();
 
         files.sort();
         files
     }
 
-    fn read_log_file(path: &Path) -> Result<Vec<Order>> {
+    fn read_log_file(path: &Path) -> Result<Vec<WalEntry>> {
         let file = OpenOptions::new().read(true).open(path)?;
         let file = BufReader::new(file);
 
@@ -77,7 +157,7 @@ impl WriteAheadLog {
             .enumerate()
             .filter_map(|(index, line)| {
                 line.map_err(anyhow::Error::from)
-                    .and_then(|json| Self::parse_order(&json))
+                    .and_then(|json| Self::parse_entry(&json))
                     .map_err(|err| {
                         warn!(
                             "Failed to read line {} from WAL {}: {}",
@@ -91,8 +171,41 @@ impl WriteAheadLog {
             .collect())
     }
 
-    fn parse_order(json: &str) -> Result<Order> {
+    fn parse_entry(json: &str) -> Result<WalEntry> {
+        if let Ok(event) = serde_json::from_str(json) {
+            return Ok(WalEntry::Event(event));
+        }
+
         let order = serde_json::from_str(json)?;
-        Ok(order)
+        Ok(WalEntry::Order(order))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{OrderId, Side};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn should_parse_an_order() {
+        let order = Order::open_limit(OrderId(1), Side::Buy, dec!(10), dec!(100));
+        let json = serde_json::to_string(&order).unwrap();
+
+        let entry = WriteAheadLog::parse_entry(&json).unwrap();
+        assert_eq!(entry, WalEntry::Order(order));
+    }
+
+    #[test]
+    fn should_parse_an_event() {
+        let event = WalEvent::Transition {
+            state: MarketState::Auction,
+            until: Some(42),
+            at: Some(7),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+
+        let entry = WriteAheadLog::parse_entry(&json).unwrap();
+        assert_eq!(entry, WalEntry::Event(event));
     }
 }
@@ -1,6 +1,14 @@
 export type Side = 'Buy' | 'Sell';
 
-export type OrderStatus = 'Open' | 'Filled' | 'PartiallyFilled';
+export type OrderStatus =
+  | 'Open'
+  | 'Filled'
+  | 'PartiallyFilled'
+  | 'Cancelled'
+  | 'CancelledNoLiquidity'
+  | 'CancelledPriceBand'
+  | 'Rejected'
+  | 'Expired';
 
 export interface Order {
   created_at: number;